@@ -0,0 +1,27 @@
+// build.rs
+use std::process::Command;
+
+/// Output of a command, or "unknown" if it can't be run, e.g. when building
+/// from a source tarball without git.
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|stdout| stdout.trim().to_string())
+        .filter(|stdout| !stdout.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn main() {
+    let git_sha = command_output("git", &["rev-parse", "--short=12", "HEAD"]);
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    println!("cargo:rustc-env=BUILD_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
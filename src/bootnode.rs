@@ -1,22 +1,37 @@
 //src/bootnode.rs
 use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use fs2::FileExt;
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, VecDeque},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
     process::{Child, Command, Stdio},
-    sync::atomic::{AtomicU16, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, PoisonError,
+    },
     time::{Duration, Instant},
 };
-use tokio::time::sleep;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{lookup_host, TcpStream},
+    time::{sleep, timeout},
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
+use url::Url;
 
 use crate::{
-    cli::Cli,
-    metrics::{MetricsResult, MetricsStatus, TestResult, TestStatus},
+    cli::{Cli, IpFamily, MetricNames, NetworkConfig, SyncMode, TestMode, DEFAULT_NODE_PROFILE},
+    metrics::{DnsResolution, MetricsResult, MetricsStatus, TestResult, TestStatus},
 };
 
-const MIN_PORT: u16 = 49152;
-const MAX_PORT: u16 = 65535;
+/// Lowest port handed to test nodes, the start of the ephemeral range.
+pub const MIN_PORT: u16 = 49152;
+pub const MAX_PORT: u16 = 65535;
 const EMOJI_SUCCESS: &str = "✅";
 const EMOJI_ERROR: &str = "❌";
 const EMOJI_WARNING: &str = "⚠️";
@@ -24,29 +39,918 @@ const EMOJI_LOADING: &str = "⏳";
 const EMOJI_ROCKET: &str = "🚀";
 const EMOJI_NETWORK: &str = "🌐";
 
-pub(crate) static NEXT_PORT: AtomicU16 = AtomicU16::new(MIN_PORT);
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Grace on top of `--timeout` for node startup and metrics retries before a
+/// test is cut off.
+const NODE_DEADLINE_SLACK: Duration = Duration::from_secs(15);
+const METRICS_READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const NODE_LOG_FILE: &str = "node.log";
+/// Locked by the instance whose node uses the data dir, for as long as it does.
+const DATA_DIR_LOCK_FILE: &str = ".bootyspector.lock";
+const NODE_LOG_TAIL_LINES: usize = 50;
+/// Logged by substrate when the bootnode's key doesn't match its advertised `/p2p/` id.
+const PEER_ID_MISMATCH_PATTERN: &str = "provided a different peer ID";
+/// How long a test waits for `--min-free-mem-mb` before it is deferred.
+const MEMORY_WAIT: Duration = Duration::from_secs(60);
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    Ip4(Ipv4Addr),
+    Ip6(Ipv6Addr),
+    Dns(String),
+    Dns4(String),
+    Dns6(String),
+}
+
+impl Host {
+    /// Host part as understood by `lookup_host`.
+    pub fn lookup_name(&self) -> String {
+        match self {
+            Host::Ip4(ip) => ip.to_string(),
+            Host::Ip6(ip) => ip.to_string(),
+            Host::Dns(name) | Host::Dns4(name) | Host::Dns6(name) => name.clone(),
+        }
+    }
+
+    pub fn is_dns(&self) -> bool {
+        matches!(self, Host::Dns(_) | Host::Dns4(_) | Host::Dns6(_))
+    }
+
+    /// Whether a resolved address matches the family this host asks for.
+    fn accepts(&self, ip: &IpAddr) -> bool {
+        match self {
+            Host::Dns4(_) => ip.is_ipv4(),
+            Host::Dns6(_) => ip.is_ipv6(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Ws,
+    Wss,
+    Quic,
+    WebRtc,
+}
+
+impl Transport {
+    pub fn is_tcp(&self) -> bool {
+        matches!(self, Transport::Tcp | Transport::Ws | Transport::Wss)
+    }
+
+    /// Protocol family used as a metric label.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Ws | Transport::Wss => "websocket",
+            Transport::Quic => "quic",
+            Transport::WebRtc => "webrtc",
+        }
+    }
+}
+
+/// A bootnode multiaddr broken into the parts we need for testing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootnodeAddr {
+    pub host: Host,
+    pub port: u16,
+    pub transport: Transport,
+    pub peer_id: String,
+}
+
+/// Parses a bootnode multiaddr such as `/dns/host/tcp/30333/wss/p2p/<peerid>`.
+///
+/// Accepts ip4/ip6/dns/dns4/dns6 hosts, plain tcp, `/tcp/<port>/ws`,
+/// `/tcp/<port>/wss` (or `/tls/ws`), `/udp/<port>/quic(-v1)` and
+/// `/udp/<port>/webrtc-direct`, and requires a trailing `/p2p/<peerid>`.
+pub fn parse_multiaddr(addr: &str) -> Result<BootnodeAddr> {
+    let rest = addr
+        .trim()
+        .strip_prefix('/')
+        .context("Multiaddr must start with '/'")?;
+    let mut parts = rest.split('/');
+    let mut value = |protocol: &str| {
+        parts
+            .next()
+            .filter(|v| !v.is_empty())
+            .with_context(|| format!("Missing value for /{}", protocol))
+    };
+
+    let host = match value("host")? {
+        "ip4" => Host::Ip4(value("ip4")?.parse().context("Invalid IPv4 address")?),
+        "ip6" => Host::Ip6(value("ip6")?.parse().context("Invalid IPv6 address")?),
+        "dns" => Host::Dns(value("dns")?.to_string()),
+        "dns4" => Host::Dns4(value("dns4")?.to_string()),
+        "dns6" => Host::Dns6(value("dns6")?.to_string()),
+        other => anyhow::bail!("Unsupported host protocol /{}", other),
+    };
+
+    let (udp, port) = match value("transport")? {
+        "tcp" => (false, value("tcp")?),
+        "udp" => (true, value("udp")?),
+        other => anyhow::bail!("Unsupported transport protocol /{}", other),
+    };
+    let port: u16 = port.parse().context("Invalid port")?;
+    if port == 0 {
+        anyhow::bail!("Port must be nonzero");
+    }
+
+    let mut transport = if udp { None } else { Some(Transport::Tcp) };
+    let mut peer_id = None;
+    while let Ok(protocol) = value("protocol") {
+        match (protocol, udp) {
+            ("ws", false) if transport == Some(Transport::Tcp) => transport = Some(Transport::Ws),
+            ("wss", false) if transport == Some(Transport::Tcp) => transport = Some(Transport::Wss),
+            // `/tls/ws` is the long form of `/wss`
+            ("tls", false) if transport == Some(Transport::Tcp) => {
+                if value("tls")? != "ws" {
+                    anyhow::bail!("Expected /ws after /tls");
+                }
+                transport = Some(Transport::Wss);
+            }
+            ("quic" | "quic-v1", true) if transport.is_none() => transport = Some(Transport::Quic),
+            ("webrtc-direct", true) if transport.is_none() => transport = Some(Transport::WebRtc),
+            ("certhash", true) if transport == Some(Transport::WebRtc) => {
+                value("certhash")?;
+            }
+            ("p2p" | "ipfs", _) => {
+                peer_id = Some(value(protocol)?.to_string());
+                break;
+            }
+            (other, _) => anyhow::bail!("Unexpected protocol /{} in multiaddr", other),
+        }
+    }
+
+    let transport = transport.context("Missing /quic or /webrtc-direct after /udp")?;
+    let peer_id = peer_id.context("Missing /p2p/<peerid> component")?;
+    if parts.next().is_some() {
+        anyhow::bail!("Unexpected components after /p2p/{}", peer_id);
+    }
+    if peer_id.len() < 46 || !peer_id.chars().all(|c| BASE58_ALPHABET.contains(c)) {
+        anyhow::bail!("Invalid peer id {}", peer_id);
+    }
+
+    Ok(BootnodeAddr {
+        host,
+        port,
+        transport,
+        peer_id,
+    })
+}
+
+/// Resolves the bootnode host to socket addresses of the requested family.
+pub async fn resolve_bootnode(addr: &BootnodeAddr) -> Result<Vec<SocketAddr>> {
+    let name = addr.host.lookup_name();
+    let resolved: Vec<SocketAddr> =
+        timeout(PREFLIGHT_TIMEOUT, lookup_host((name.as_str(), addr.port)))
+            .await
+            .with_context(|| format!("Timed out resolving {}", name))?
+            .with_context(|| format!("Failed to resolve {}", name))?
+            .filter(|socket_addr| addr.host.accepts(&socket_addr.ip()))
+            .collect();
+
+    if resolved.is_empty() {
+        anyhow::bail!("No usable addresses found for {}", name);
+    }
+    Ok(resolved)
+}
+
+/// Tries a plain TCP connect to each address until one succeeds.
+pub async fn connect_any(addrs: &[SocketAddr]) -> Result<SocketAddr> {
+    let mut last_error = None;
+    for socket_addr in addrs {
+        match timeout(PREFLIGHT_TIMEOUT, TcpStream::connect(socket_addr)).await {
+            Ok(Ok(_)) => return Ok(*socket_addr),
+            Ok(Err(e)) => last_error = Some(format!("{}: {}", socket_addr, e)),
+            Err(_) => last_error = Some(format!("{}: connect timed out", socket_addr)),
+        }
+    }
+    Err(anyhow::anyhow!(
+        "TCP connect failed ({})",
+        last_error.unwrap_or_else(|| "no addresses".to_string())
+    ))
+}
+
+/// "v4" or "v6", used as the `ip_family` label.
+pub fn ip_family(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "v4",
+        IpAddr::V6(_) => "v6",
+    }
+}
+
+/// Connects over IPv4 and IPv6 separately, so a broken path for one family is
+/// visible even when the other works. Returns whether each family present in
+/// `addrs` was reachable, and the first reachable address in resolver order.
+pub async fn connect_by_family(
+    addrs: &[SocketAddr],
+) -> (BTreeMap<String, bool>, Result<SocketAddr>) {
+    let (v4, v6): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.iter().partition(|a| a.is_ipv4());
+    let connect = |family: Vec<SocketAddr>| async move {
+        if family.is_empty() {
+            None
+        } else {
+            Some(connect_any(&family).await)
+        }
+    };
+    let (v4, v6) = futures::future::join(connect(v4), connect(v6)).await;
 
-pub fn get_next_port() -> u16 {
-    let current = NEXT_PORT.load(Ordering::Relaxed);
-    let next = if current >= MAX_PORT {
-        MIN_PORT
-    } else {
-        current + 1
+    let mut families = BTreeMap::new();
+    let mut connected = Vec::new();
+    let mut errors = Vec::new();
+    for (family, result) in [("v4", v4), ("v6", v6)] {
+        match result {
+            Some(Ok(addr)) => {
+                families.insert(family.to_string(), true);
+                connected.push(addr);
+            }
+            Some(Err(e)) => {
+                families.insert(family.to_string(), false);
+                errors.push(format!("{}: {}", family, e));
+            }
+            None => {}
+        }
+    }
+
+    let preferred = addrs
+        .iter()
+        .find(|addr| connected.contains(addr))
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("{}", errors.join("; ")));
+    (families, preferred)
+}
+
+/// Opens a TCP connection to `host`, through `proxy` if given. A `socks5://`
+/// proxy is handed an address resolved here, `socks5h://` and `http://`
+/// proxies resolve hostnames themselves.
+pub async fn connect_tcp(proxy: Option<&Url>, host: &str, port: u16) -> Result<TcpStream> {
+    let Some(proxy) = proxy else {
+        return TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("TCP connect to {}:{} failed", host, port));
     };
-    NEXT_PORT.store(next, Ordering::Relaxed);
-    current
+    let proxy_host = proxy.host_str().context("Proxy URL has no host")?;
+    // socks has no default port known to `url`
+    let proxy_port = proxy.port_or_known_default().unwrap_or(1080);
+
+    match proxy.scheme() {
+        "socks5" | "socks5h" => {
+            let target = if proxy.scheme() == "socks5" && host.parse::<IpAddr>().is_err() {
+                lookup_host((host, port))
+                    .await?
+                    .next()
+                    .with_context(|| format!("Failed to resolve {}", host))?
+                    .ip()
+                    .to_string()
+            } else {
+                host.to_string()
+            };
+            let proxy_addr = (proxy_host, proxy_port);
+            let stream = match proxy.password() {
+                Some(password) => {
+                    tokio_socks::tcp::Socks5Stream::connect_with_password(
+                        proxy_addr,
+                        (target.as_str(), port),
+                        proxy.username(),
+                        password,
+                    )
+                    .await
+                }
+                None => {
+                    tokio_socks::tcp::Socks5Stream::connect(proxy_addr, (target.as_str(), port))
+                        .await
+                }
+            }
+            .with_context(|| {
+                format!(
+                    "SOCKS5 connect to {}:{} via {} failed",
+                    host, port, proxy_host
+                )
+            })?;
+            Ok(stream.into_inner())
+        }
+        "http" => {
+            let mut stream = TcpStream::connect((proxy_host, proxy_port))
+                .await
+                .with_context(|| format!("TCP connect to proxy {} failed", proxy_host))?;
+            // IPv6 literals need brackets in the authority
+            let authority = match host.parse::<IpAddr>() {
+                Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+                _ => format!("{}:{}", host, port),
+            };
+            let request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", authority);
+            stream.write_all(request.as_bytes()).await?;
+            let status_line = read_response_head(&mut stream).await?;
+            if status_line.split_whitespace().nth(1) != Some("200") {
+                anyhow::bail!("Proxy refused CONNECT to {}: {}", authority, status_line);
+            }
+            Ok(stream)
+        }
+        scheme => anyhow::bail!("Unsupported proxy scheme {}", scheme),
+    }
+}
+
+/// Opens a connection the way a client of the bootnode would and returns how
+/// long it took: the TCP connect, plus the TLS handshake for `/wss` and the
+/// HTTP upgrade for websocket transports. `target` is the address to connect
+/// to, normally one of the resolved IPs.
+pub async fn handshake(addr: &BootnodeAddr, target: &str, proxy: Option<&Url>) -> Result<Duration> {
+    let start = Instant::now();
+    let stream = connect_tcp(proxy, target, addr.port).await?;
+
+    let host = addr.host.lookup_name();
+    match addr.transport {
+        Transport::Ws => websocket_upgrade(stream, &host, "/").await?,
+        Transport::Wss => {
+            let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+            let stream = connector
+                .connect(&host, stream)
+                .await
+                .context("TLS handshake failed")?;
+            websocket_upgrade(stream, &host, "/").await?;
+        }
+        _ => {}
+    }
+
+    Ok(start.elapsed())
+}
+
+/// Relay chain RPC a parachain node of this network connects to, `None` for
+/// relay chain nodes.
+fn relay_rpc(cli: &Cli, network: &str, network_config: &NetworkConfig) -> Result<Option<String>> {
+    // encointer runs as a cumulus collator, so it needs a relay chain like any parachain
+    if !matches!(
+        network_config.command_id.as_str(),
+        "parachain" | "encointer"
+    ) {
+        return Ok(None);
+    }
+    let relay = network
+        .split('-')
+        .next_back()
+        .context("Invalid network name")?;
+    Ok(Some(network_config.relay_rpc_url(cli, relay)))
+}
+
+/// Checks that a relay chain RPC endpoint accepts websocket connections, so a
+/// parachain node failing for lack of a relay chain isn't blamed on the bootnode.
+/// With `family` set only addresses of that family are tried. Returns the
+/// address connected to, unknown when a proxy resolved the host.
+pub async fn check_relay_rpc(
+    relay_rpc: &str,
+    family: Option<IpFamily>,
+    proxy: Option<&Url>,
+) -> Result<Option<IpAddr>> {
+    let url = Url::parse(relay_rpc).with_context(|| format!("Invalid URL {}", relay_rpc))?;
+    let host = url.host_str().context("URL has no host")?.to_string();
+    let port = url.port_or_known_default().context("URL has no port")?;
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let check = async {
+        let target = match family {
+            Some(family) => lookup_host((host.as_str(), port))
+                .await
+                .with_context(|| format!("Failed to resolve {}", host))?
+                .map(|socket_addr| socket_addr.ip())
+                .find(|ip| family.matches(ip))
+                .with_context(|| format!("{} has no {:?} address", host, family))?
+                .to_string(),
+            None => host.clone(),
+        };
+        let stream = connect_tcp(proxy, &target, port).await?;
+        let ip = match target.parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) if proxy.is_none() => stream.peer_addr().ok().map(|addr| addr.ip()),
+            Err(_) => None,
+        };
+        match url.scheme() {
+            "wss" | "https" => {
+                let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+                let stream = connector
+                    .connect(&host, stream)
+                    .await
+                    .context("TLS handshake failed")?;
+                websocket_upgrade(stream, &host, &path).await?;
+            }
+            _ => websocket_upgrade(stream, &host, &path).await?,
+        }
+        Ok::<_, anyhow::Error>(ip)
+    };
+    timeout(PREFLIGHT_TIMEOUT, check)
+        .await
+        .with_context(|| format!("{} timed out", relay_rpc))?
+        .with_context(|| relay_rpc.to_string())
+}
+
+async fn websocket_upgrade<S>(mut stream: S, host: &str, path: &str) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let status_line = read_response_head(&mut stream).await?;
+    if status_line.split_whitespace().nth(1) != Some("101") {
+        anyhow::bail!("WebSocket upgrade rejected: {}", status_line);
+    }
+    Ok(())
+}
+
+/// Reads an HTTP response head and returns its status line.
+async fn read_response_head<S>(stream: &mut S) -> Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    const MAX_RESPONSE_HEAD: usize = 8192;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            anyhow::bail!("Connection closed before the HTTP response");
+        }
+        response.extend_from_slice(&buf[..read]);
+        if response.len() > MAX_RESPONSE_HEAD {
+            anyhow::bail!("HTTP response head too large");
+        }
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    Ok(response.lines().next().unwrap_or_default().to_string())
+}
+
+/// Leading `major.minor.patch` of a node version like `1.17.1-7c4cd60da6d`,
+/// missing parts count as 0.
+pub fn parse_node_version(version: &str) -> Option<[u64; 3]> {
+    let mut parts = [0; 3];
+    let release = version.split(['-', '+']).next()?;
+    for (i, part) in release.split('.').enumerate() {
+        *parts.get_mut(i)? = part.parse().ok()?;
+    }
+    Some(parts)
+}
+
+/// Free ports handed out to spawned nodes. Released ports go to the back of
+/// the queue so a port is reused as late as possible (avoids TIME_WAIT).
+static PORT_POOL: Mutex<VecDeque<u16>> = Mutex::new(VecDeque::new());
+
+/// `--version` output of each node binary, looked up once per binary. `None`
+/// if the binary couldn't be run. Held across the lookup, so concurrent tests
+/// wait for the first one instead of all running the binary.
+static BINARY_VERSIONS: tokio::sync::Mutex<BTreeMap<PathBuf, Option<String>>> =
+    tokio::sync::Mutex::const_new(BTreeMap::new());
+
+/// Version of a node binary, e.g. "1.16.0-87971b3e927" for a `--version`
+/// output of "polkadot 1.16.0-87971b3e927".
+pub async fn binary_version(binary: &Path) -> Option<String> {
+    let mut versions = BINARY_VERSIONS.lock().await;
+    if let Some(version) = versions.get(binary) {
+        return version.clone();
+    }
+    let version = async {
+        let output = tokio::process::Command::new(binary)
+            .arg("--version")
+            .output()
+            .await
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.split_whitespace().last().map(str::to_string)
+    }
+    .await;
+    versions.insert(binary.to_path_buf(), version.clone());
+    version
+}
+
+/// Client for scraping test nodes' metrics, shared by all nodes so its
+/// connection pool is reused instead of rebuilt on every poll.
+static METRICS_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    // sends Accept-Encoding and decompresses transparently; nodes that don't
+    // compress their metrics just answer uncompressed
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .connect_timeout(Duration::from_secs(5))
+        .gzip(true)
+        .deflate(true)
+        .build()
+        .expect("Failed to build metrics HTTP client")
+});
+
+/// Set once any node reported the `discovered` metric.
+static DISCOVERED_METRIC_SEEN: AtomicBool = AtomicBool::new(false);
+
+/// Whether any node so far reported the metric configured as `discovered`.
+/// If none did, the node's metric names likely changed.
+pub fn discovered_metric_seen() -> bool {
+    DISCOVERED_METRIC_SEEN.load(Ordering::Relaxed)
+}
+
+/// Binary that runs nodes of networks with this `commandId`.
+pub fn node_binary<'a>(cli: &'a Cli, command_id: &str) -> &'a Path {
+    match command_id {
+        "parachain" => &cli.parachain_binary,
+        "encointer" => &cli.encointer_binary,
+        _ => &cli.polkadot_binary,
+    }
+}
+
+/// Fills the port pool from `base_port`, leaving out `reserved` ports that are
+/// in use elsewhere, like the metrics server's. Must be called before any test runs.
+pub fn init_port_pool(base_port: u16, reserved: &[u16]) {
+    let mut pool = PORT_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    pool.clear();
+    pool.extend((base_port..=MAX_PORT).filter(|port| !reserved.contains(port)));
+}
+
+/// Takes a (prometheus, p2p) port pair from the pool under a single lock.
+fn acquire_ports() -> Result<(u16, u16)> {
+    let mut pool = PORT_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    if pool.len() < 2 {
+        anyhow::bail!("No free ports left in the pool");
+    }
+    let prometheus_port = pool.pop_front().context("Port pool exhausted")?;
+    let p2p_port = pool.pop_front().context("Port pool exhausted")?;
+    Ok((prometheus_port, p2p_port))
+}
+
+fn release_ports(ports: &[u16]) {
+    let mut pool = PORT_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    pool.extend(ports);
+}
+
+/// Value of `label` in a series like `name{label="value",...}`.
+fn metric_label<'a>(series: &'a str, label: &str) -> Option<&'a str> {
+    let labels = series.split_once('{')?.1.strip_suffix('}')?;
+    labels.split(',').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key.trim() == label).then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Why a bootnode test failed. The message goes into `error_details`, while
+/// the variant decides the bounded `TestStatus` used for metric labels.
+#[derive(Debug, thiserror::Error)]
+pub enum BootnodeError {
+    #[error("invalid multiaddr: {0}")]
+    ParseError(String),
+    #[error("DNS resolution failed: {0}")]
+    DnsFailed(String),
+    #[error("bootnode unreachable: {0}")]
+    Unreachable(String),
+    #[error("node failed to start: {0}")]
+    SpawnFailed(String),
+    #[error("node metrics server didn't come up within {0}s")]
+    MetricsServerDown(u64),
+    #[error("node metrics unreachable: {0}")]
+    MetricsUnreachable(String),
+    #[error("node exposes no peer metrics")]
+    NoMetrics,
+    #[error("timed out waiting for peers")]
+    Timeout,
+    #[error("bootnode peer id doesn't match its multiaddr: {0}")]
+    PeerIdMismatch(String),
+    #[error("relay chain RPC unavailable: {0}")]
+    RelayRpcUnavailable(String),
+    #[error("deferred, only {available_mb} MB of memory available")]
+    Deferred { available_mb: u64 },
+}
+
+impl BootnodeError {
+    pub fn status(&self) -> TestStatus {
+        match self {
+            Self::ParseError(_) => TestStatus::InvalidMultiaddr,
+            Self::DnsFailed(_) => TestStatus::DnsResolutionFailed,
+            Self::Unreachable(_) => TestStatus::Unreachable,
+            Self::SpawnFailed(_) => TestStatus::NodeStartupFailed,
+            Self::MetricsServerDown(_) => TestStatus::MetricsServerDown,
+            Self::MetricsUnreachable(_) => TestStatus::MetricsUnavailable,
+            Self::NoMetrics => TestStatus::NoMetricFound,
+            Self::Timeout => TestStatus::Timeout,
+            Self::PeerIdMismatch(_) => TestStatus::PeerIdMismatch,
+            Self::RelayRpcUnavailable(_) => TestStatus::RelayRpcUnavailable,
+            Self::Deferred { .. } => TestStatus::Deferred,
+        }
+    }
+}
+
+/// What `bootnode_is_working` observed while polling the node.
+#[derive(Debug)]
+struct NodeCheck {
+    peers: u64,
+    error: Option<BootnodeError>,
+    sync_progress: Option<f64>,
+    inbound_peers: Option<u64>,
+    outbound_peers: Option<u64>,
+    peer_counts: BTreeMap<String, u64>,
+    time_to_first_peer_ms: Option<u64>,
+}
+
+impl NodeCheck {
+    fn new() -> Self {
+        Self {
+            peers: 0,
+            error: None,
+            sync_progress: None,
+            inbound_peers: None,
+            outbound_peers: None,
+            peer_counts: BTreeMap::new(),
+            time_to_first_peer_ms: None,
+        }
+    }
+
+    /// Keeps the latest peer count and secondary metrics across polls, and
+    /// when the node first saw a peer, `since_start` after it was spawned.
+    fn observe(&mut self, metrics: &MetricsResult, since_start: Duration) {
+        if metrics.peers > 0 && self.time_to_first_peer_ms.is_none() {
+            self.time_to_first_peer_ms = Some(since_start.as_millis() as u64);
+        }
+        if matches!(metrics.status, MetricsStatus::Available) {
+            self.peers = metrics.peers;
+        }
+        self.sync_progress = metrics.sync_progress.or(self.sync_progress);
+        self.inbound_peers = metrics.inbound_peers.or(self.inbound_peers);
+        self.outbound_peers = metrics.outbound_peers.or(self.outbound_peers);
+        self.peer_counts.extend(
+            metrics
+                .peer_counts
+                .iter()
+                .map(|(metric, count)| (metric.clone(), *count)),
+        );
+    }
+
+    fn finish(&mut self, peers: u64, error: Option<BootnodeError>) {
+        self.peers = peers;
+        self.error = error;
+    }
+
+    fn status(&self) -> TestStatus {
+        self.error
+            .as_ref()
+            .map_or(TestStatus::Success, BootnodeError::status)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ResourceUsage {
+    rss_bytes: u64,
+    cpu_time_ms: u64,
 }
 
 #[derive(Debug)]
 pub struct NodeProcess {
     process: Child,
     data_dir: PathBuf,
+    /// held while the node runs, so no startup sweep removes the data dir
+    _data_dir_lock: std::fs::File,
+    log_file: Option<PathBuf>,
+    started_at: Instant,
+    startup_grace: Duration,
+    metrics_retries: u32,
+    metrics_initial_backoff: Duration,
+    /// `--max-metrics-failures`
+    max_metrics_failures: u32,
+    /// `--peer-metrics`
+    peer_metrics: Vec<String>,
+    metric_names: MetricNames,
     prometheus_port: u16,
     p2p_port: u16,
     operator: String,
     network: String,
     bootnode: String,
-    cli: Cli,
+}
+
+/// Per-node data dir. The p2p port is unique among live nodes, so concurrent
+/// tests of the same operator and network never share a database.
+pub fn node_data_dir(data_dir: &Path, operator: &str, network: &str, p2p_port: u16) -> PathBuf {
+    data_dir.join(format!("{}_{}_{}", operator, network, p2p_port))
+}
+
+const QUARANTINE_PREFIX: &str = "failed-";
+
+/// Where a failed node's data dir is kept for inspection. Unique per test, so
+/// a later test on the same port never opens it.
+fn quarantine_data_dir(node_data_dir: &Path) -> PathBuf {
+    let name = node_data_dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    node_data_dir.with_file_name(format!(
+        "{}{}-{}",
+        QUARANTINE_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        name
+    ))
+}
+
+/// Takes the lock that marks `node_data_dir` as in use by a live node. Released
+/// when the file is dropped, or by the OS if the process dies.
+fn lock_data_dir(node_data_dir: &Path) -> Result<std::fs::File> {
+    let path = node_data_dir.join(DATA_DIR_LOCK_FILE);
+    let file = std::fs::File::create(&path)
+        .with_context(|| format!("Failed to create lock file {:?}", path))?;
+    file.try_lock_exclusive()
+        .with_context(|| format!("Data dir {:?} is in use", node_data_dir))?;
+    Ok(file)
+}
+
+/// Whether a live node, of this or another instance, holds the lock of
+/// `node_data_dir`.
+fn data_dir_in_use(node_data_dir: &Path) -> bool {
+    match std::fs::File::open(node_data_dir.join(DATA_DIR_LOCK_FILE)) {
+        Ok(file) => file.try_lock_exclusive().is_err(),
+        Err(_) => false,
+    }
+}
+
+/// Removes node data dirs left behind by runs that died before cleaning up.
+/// Dirs kept by `--keep-failed-data` go too unless `keep_failed` is set, so a
+/// restart doesn't destroy them while the flag is on. Dirs locked by a live
+/// node, e.g. of another instance sharing the data dir, are left alone.
+pub fn remove_stale_data_dirs(data_dir: &Path, keep_failed: bool) -> Result<usize> {
+    let entries = match std::fs::read_dir(data_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", data_dir)),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let stale = if is_quarantined_data_dir(&name) {
+            !keep_failed
+        } else {
+            is_node_data_dir(&name)
+        };
+        if !entry.file_type()?.is_dir() || !stale {
+            continue;
+        }
+        if data_dir_in_use(&entry.path()) {
+            continue;
+        }
+        match std::fs::remove_dir_all(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove stale data dir {:?}: {}", entry.path(), e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Whether `name` looks like a [`quarantine_data_dir`]:
+/// `failed-{timestamp}-{node data dir}`.
+fn is_quarantined_data_dir(name: &std::ffi::OsStr) -> bool {
+    name.to_str()
+        .and_then(|name| name.strip_prefix(QUARANTINE_PREFIX))
+        .and_then(|rest| rest.split_once('-'))
+        .is_some_and(|(_, name)| is_node_data_dir(name.as_ref()))
+}
+
+/// Whether `name` looks like a [`node_data_dir`]: `{operator}_{network}_{port}`.
+fn is_node_data_dir(name: &std::ffi::OsStr) -> bool {
+    let Some(name) = name.to_str() else {
+        return false;
+    };
+    let mut parts = name.rsplitn(3, '_');
+    let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+    port.is_some() && parts.filter(|part| !part.is_empty()).count() == 2
+}
+
+/// Total size of the files under `path`.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Waits up to `MEMORY_WAIT` for the host to have `min_free_mb` of memory
+/// available, so a burst of node starts degrades into deferred tests rather
+/// than OOM kills. Returns the available memory in MB if it never got there.
+async fn wait_for_free_memory(min_free_mb: u64, shutdown: &CancellationToken) -> Result<(), u64> {
+    let mut system = System::new();
+    let deadline = Instant::now() + MEMORY_WAIT;
+    loop {
+        system.refresh_memory();
+        let available_mb = system.available_memory() / (1024 * 1024);
+        if available_mb >= min_free_mb {
+            return Ok(());
+        }
+        if Instant::now() >= deadline || shutdown.is_cancelled() {
+            return Err(available_mb);
+        }
+        tokio::select! {
+            _ = sleep(MEMORY_POLL_INTERVAL) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+}
+
+/// Binary, chain spec and arguments for a network's nodes, resolved before any
+/// ports or directories are claimed.
+#[derive(Debug)]
+pub struct NodePlan {
+    pub binary: PathBuf,
+    /// argument template of the network's node profile
+    pub args: Vec<String>,
+    pub chain_spec: PathBuf,
+    pub relay_rpc: Option<String>,
+    pub sync_mode: Option<SyncMode>,
+    pub extra_args: Vec<String>,
+}
+
+impl NodePlan {
+    pub fn new(cli: &Cli, network: &str, network_config: &NetworkConfig) -> Result<Self> {
+        let relay_rpc = relay_rpc(cli, network, network_config)?;
+        let binary = node_binary(cli, &network_config.command_id);
+
+        let chain_spec = network_config.chain_spec(&cli.chain_spec_dir, network);
+        if !chain_spec.exists() {
+            anyhow::bail!("Chain spec file does not exist: {:?}", chain_spec);
+        }
+
+        let profile = network_config
+            .profile
+            .as_deref()
+            .unwrap_or(DEFAULT_NODE_PROFILE);
+
+        Ok(Self {
+            binary: binary.to_path_buf(),
+            args: cli.node_profile(profile)?,
+            chain_spec,
+            relay_rpc,
+            sync_mode: network_config.sync_mode(cli),
+            extra_args: cli
+                .extra_node_args
+                .iter()
+                .chain(&network_config.extra_args)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Node command from the profile's arguments, followed by the extra args.
+    /// Profiles can use the placeholders `{prometheus_port}`, `{p2p_port}`,
+    /// `{data_dir}`, `{chain_spec}`, `{bootnode}`, `{relay_rpc}` and `{sync_mode}`.
+    pub fn command(
+        &self,
+        bootnode: &str,
+        prometheus_port: u16,
+        p2p_port: u16,
+        data_dir: &Path,
+    ) -> Command {
+        let prometheus_port = prometheus_port.to_string();
+        let p2p_port = p2p_port.to_string();
+        let data_dir = data_dir.to_string_lossy();
+        let chain_spec = self.chain_spec.to_string_lossy();
+        let values = [
+            ("{prometheus_port}", Some(prometheus_port.as_str())),
+            ("{p2p_port}", Some(p2p_port.as_str())),
+            ("{data_dir}", Some(&*data_dir)),
+            ("{chain_spec}", Some(&*chain_spec)),
+            ("{bootnode}", Some(bootnode)),
+            ("{relay_rpc}", self.relay_rpc.as_deref()),
+            ("{sync_mode}", self.sync_mode.map(|mode| mode.as_str())),
+        ];
+
+        let mut cmd = Command::new(&self.binary);
+        cmd.args(self.args.iter().filter_map(|arg| expand_arg(arg, &values)));
+        cmd.args(&self.extra_args);
+        cmd
+    }
+}
+
+/// Fills the placeholders in a profile argument. Arguments using a value this
+/// node doesn't have, like `{relay_rpc}` for a relay chain node, are left out.
+fn expand_arg(arg: &str, values: &[(&str, Option<&str>)]) -> Option<String> {
+    let mut arg = arg.to_string();
+    for (placeholder, value) in values {
+        if arg.contains(placeholder) {
+            arg = arg.replace(placeholder, (*value)?);
+        }
+    }
+    Some(arg)
 }
 
 pub async fn spawn_node(
@@ -54,102 +958,190 @@ pub async fn spawn_node(
     operator: &str,
     network: &str,
     bootnode: &str,
-    command_id: &str,
+    network_config: &NetworkConfig,
 ) -> Result<NodeProcess> {
-    let data_dir = cli.data_dir.join(format!("{}_{}", operator, network));
-    std::fs::create_dir_all(&data_dir)?;
+    let plan = NodePlan::new(cli, network, network_config)?;
 
-    let relaychain = if command_id == "parachain" {
-        Some(network.split('-').last().context("Invalid network name")?)
-    } else {
-        None
-    };
+    if let Some(budget) = cli.max_data_dir_bytes {
+        let used = dir_size(&cli.data_dir);
+        if used > budget {
+            anyhow::bail!(
+                "Data dir {:?} holds {} bytes, over the --max-data-dir-bytes budget of {}",
+                cli.data_dir,
+                used,
+                budget
+            );
+        }
+    }
 
-    let binary = if command_id == "parachain" {
-        &cli.parachain_binary
-    } else {
-        &cli.polkadot_binary
-    };
+    let (prometheus_port, p2p_port) = acquire_ports()?;
 
-    let chain_spec = cli.chain_spec_dir.join(format!("{}.json", network));
-    if !chain_spec.exists() {
-        anyhow::bail!("Chain spec file does not exist: {:?}", chain_spec);
+    let data_dir = node_data_dir(&cli.data_dir, operator, network, p2p_port);
+    if let Err(e) = std::fs::create_dir_all(&data_dir) {
+        release_ports(&[prometheus_port, p2p_port]);
+        return Err(e).with_context(|| format!("Failed to create data dir {:?}", data_dir));
     }
-
-    let prometheus_port = get_next_port();
-    let p2p_port = get_next_port();
+    let data_dir_lock = match lock_data_dir(&data_dir) {
+        Ok(lock) => lock,
+        Err(e) => {
+            release_ports(&[prometheus_port, p2p_port]);
+            return Err(e);
+        }
+    };
 
     info!(
         "{} Starting node for {}/{} {} prometheus: {}, p2p: {}",
         EMOJI_ROCKET, operator, network, EMOJI_NETWORK, prometheus_port, p2p_port
     );
 
-    let mut cmd = Command::new(binary);
-    cmd.args(&[
-        "--no-hardware-benchmarks",
-        "--no-mdns",
-        "--prometheus-external",
-        &format!("--prometheus-port={}", prometheus_port),
-        &format!("--port={}", p2p_port),
-        "-d",
-    ])
-    .arg(&data_dir)
-    .arg("--chain")
-    .arg(&chain_spec)
-    .arg("--bootnodes")
-    .arg(bootnode);
-
-    if let Some(relay) = relaychain {
-        cmd.arg("--relay-chain-rpc-urls")
-            .arg(format!("wss://{}.dotters.network/", relay));
-    }
-
-    let process = cmd
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .context("Failed to spawn node process")?;
+    let mut cmd = plan.command(bootnode, prometheus_port, p2p_port, &data_dir);
+
+    // substrate logs to stderr; keep it only when asked to avoid disk churn
+    let log_file = cli.capture_logs.then(|| data_dir.join(NODE_LOG_FILE));
+    let stderr = match &log_file {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(file) => Stdio::from(file),
+            Err(e) => {
+                release_ports(&[prometheus_port, p2p_port]);
+                let _ = std::fs::remove_dir_all(&data_dir);
+                return Err(e).context("Failed to create node log file");
+            }
+        },
+        None => Stdio::null(),
+    };
+
+    let process = match cmd.stdout(Stdio::null()).stderr(stderr).spawn() {
+        Ok(process) => process,
+        Err(e) => {
+            release_ports(&[prometheus_port, p2p_port]);
+            let _ = std::fs::remove_dir_all(&data_dir);
+            return Err(e).context("Failed to spawn node process");
+        }
+    };
 
     Ok(NodeProcess {
         process,
         data_dir,
+        _data_dir_lock: data_dir_lock,
+        log_file,
+        started_at: Instant::now(),
+        startup_grace: network_config.startup_grace(cli),
+        metrics_retries: cli.metrics_retries,
+        metrics_initial_backoff: Duration::from_millis(cli.metrics_initial_backoff_ms),
+        max_metrics_failures: cli.max_metrics_failures,
+        peer_metrics: cli.peer_metrics.clone(),
+        metric_names: cli.metric_names.clone(),
         prometheus_port,
         p2p_port,
         bootnode: bootnode.to_string(),
         operator: operator.to_string(),
         network: network.to_string(),
-        cli: cli.clone(),
     })
 }
 
 impl NodeProcess {
-    pub async fn cleanup(mut self) -> Result<()> {
+    /// First line of the captured node log containing `pattern`.
+    pub fn find_in_log(&self, pattern: &str) -> Option<String> {
+        let content = std::fs::read_to_string(self.log_file.as_ref()?).ok()?;
+        content
+            .lines()
+            .find(|line| line.contains(pattern))
+            .map(|line| line.trim().to_string())
+    }
+
+    /// Last `lines` lines of the captured node log, if logs are being captured.
+    pub fn tail_log(&self, lines: usize) -> Option<String> {
+        let content = std::fs::read_to_string(self.log_file.as_ref()?).ok()?;
+        let all: Vec<&str> = content.lines().collect();
+        Some(all[all.len().saturating_sub(lines)..].join("\n"))
+    }
+
+    /// Memory and CPU the node has used so far, while it's still running.
+    fn resource_usage(&self) -> Option<ResourceUsage> {
+        let pid = Pid::from_u32(self.process.id());
+        let mut system = System::new();
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            false,
+            ProcessRefreshKind::nothing().with_memory().with_cpu(),
+        );
+        let process = system.process(pid)?;
+        Some(ResourceUsage {
+            rss_bytes: process.memory(),
+            cpu_time_ms: process.accumulated_cpu_time(),
+        })
+    }
+
+    /// Stops the node and frees its ports. The data dir is removed unless
+    /// `keep_data` is set.
+    pub async fn cleanup(mut self, keep_data: bool) -> Result<()> {
         let _ = self.process.kill();
         sleep(Duration::from_secs(1)).await;
         if let Ok(None) = self.process.try_wait() {
             warn!("Process still running after graceful shutdown, force killing");
             let _ = self.process.kill();
         }
-        std::fs::remove_dir_all(&self.data_dir)?;
+        release_ports(&[self.prometheus_port, self.p2p_port]);
+        if keep_data {
+            // moved out of the way, as the port and with it the dir name is
+            // handed to the next test once released
+            let kept = quarantine_data_dir(&self.data_dir);
+            std::fs::rename(&self.data_dir, &kept)
+                .with_context(|| format!("Failed to move {:?} to {:?}", self.data_dir, kept))?;
+            info!(
+                "Keeping data dir of failed node for {}/{}: {:?}",
+                self.operator, self.network, kept
+            );
+        } else {
+            std::fs::remove_dir_all(&self.data_dir)?;
+        }
         Ok(())
     }
 
     fn create_metrics_result(&self, peer_data: HashMap<String, u64>) -> MetricsResult {
+        let sync_progress = match (peer_data.get("best_block"), peer_data.get("sync_target")) {
+            (Some(&best), Some(&target)) if target > 0 => {
+                Some((best as f64 / target as f64 * 100.0).min(100.0))
+            }
+            _ => None,
+        };
+        // open connections per direction = opened - closed over the node's lifetime
+        let connections = |direction: &str| {
+            let opened = peer_data.get(&format!("connections_opened_{}", direction))?;
+            let closed = peer_data
+                .get(&format!("connections_closed_{}", direction))
+                .copied()
+                .unwrap_or(0);
+            Some(opened.saturating_sub(closed))
+        };
+
+        let discovered = peer_data.get("discovered").copied();
+        if discovered.is_some() {
+            DISCOVERED_METRIC_SEEN.store(true, Ordering::Relaxed);
+        }
+
         MetricsResult {
-            peers: peer_data.get("discovered").copied().unwrap_or(0),
-            status: if peer_data.contains_key("discovered") {
+            peers: discovered.unwrap_or(0),
+            status: if discovered.is_some() {
                 MetricsStatus::Available
             } else {
                 MetricsStatus::NoMetricFound
             },
+            sync_progress,
+            inbound_peers: connections("in"),
+            outbound_peers: connections("out"),
+            peer_counts: peer_data
+                .iter()
+                .filter(|(metric, _)| self.peer_metrics.contains(metric))
+                .map(|(metric, count)| (metric.clone(), *count))
+                .collect(),
         }
     }
 
     pub async fn check_discovered_peers(&self) -> Result<MetricsResult> {
-        const MAX_RETRIES: u32 = 5;
-        const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+        let max_retries = self.metrics_retries;
 
-        for retry in 0..MAX_RETRIES {
+        for retry in 0..max_retries {
             match self.fetch_metrics().await {
                 Ok(metrics) => match self.parse_peer_metrics(&metrics) {
                     Ok(peer_data) => return Ok(self.create_metrics_result(peer_data)),
@@ -158,10 +1150,10 @@ impl NodeProcess {
                             "{} Failed to parse metrics on attempt {}/{}: {}",
                             EMOJI_WARNING,
                             retry + 1,
-                            MAX_RETRIES,
+                            max_retries,
                             e
                         );
-                        if retry == MAX_RETRIES - 1 {
+                        if retry == max_retries - 1 {
                             return Err(e);
                         }
                     }
@@ -171,34 +1163,31 @@ impl NodeProcess {
                         "{} Failed to fetch metrics on attempt {}/{}: {}",
                         EMOJI_WARNING,
                         retry + 1,
-                        MAX_RETRIES,
+                        max_retries,
                         e
                     );
-                    if retry == MAX_RETRIES - 1 {
+                    if retry == max_retries - 1 {
                         return Err(e);
                     }
                 }
             }
 
-            let backoff = INITIAL_BACKOFF * 2u32.pow(retry);
+            let backoff = self
+                .metrics_initial_backoff
+                .saturating_mul(2u32.saturating_pow(retry));
             let jitter = rand::random::<u64>() % 100;
             sleep(backoff + Duration::from_millis(jitter)).await;
         }
 
         Err(anyhow::anyhow!(
             "Failed to fetch metrics after {} attempts",
-            MAX_RETRIES
+            max_retries
         ))
     }
 
     async fn fetch_metrics(&self) -> Result<String> {
         let metrics_url = format!("http://127.0.0.1:{}/metrics", self.prometheus_port);
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(10))
-            .connect_timeout(Duration::from_secs(5))
-            .build()?;
-
-        let response = match client
+        let response = match METRICS_CLIENT
             .get(&metrics_url)
             .header("Accept", "text/plain")
             .send()
@@ -240,8 +1229,9 @@ impl NodeProcess {
             }
 
             match self.parse_metric_line(line) {
+                // series that differ only in unrelated labels are summed
                 Ok(Some((metric, count))) => {
-                    peer_data.insert(metric, count);
+                    *peer_data.entry(metric).or_insert(0) += count;
                 }
                 Ok(None) => continue,
                 Err(e) => {
@@ -273,7 +1263,8 @@ impl NodeProcess {
             return Ok(None);
         }
 
-        let metric_name = parts[0].split('{').next().unwrap_or("").trim();
+        let series = parts[0];
+        let metric_name = series.split('{').next().unwrap_or("").trim();
 
         let value_str = parts.last().unwrap_or(&"0");
 
@@ -282,50 +1273,146 @@ impl NodeProcess {
             Err(_) => return Ok(None),
         };
 
+        let names = &self.metric_names;
         match metric_name {
-            "substrate_sub_libp2p_peerset_num_discovered" => {
-                Ok(Some(("discovered".to_string(), value as u64)))
+            name if name == names.discovered => Ok(Some(("discovered".to_string(), value as u64))),
+            name if name == names.block_height => match metric_label(series, "status") {
+                Some("best") => Ok(Some(("best_block".to_string(), value as u64))),
+                Some("sync_target") => Ok(Some(("sync_target".to_string(), value as u64))),
+                _ => Ok(None),
+            },
+            name if name == names.connections_opened || name == names.connections_closed => {
+                let kind = if name == names.connections_opened {
+                    "opened"
+                } else {
+                    "closed"
+                };
+                match metric_label(series, "direction") {
+                    Some(direction @ ("in" | "out")) => Ok(Some((
+                        format!("connections_{}_{}", kind, direction),
+                        value as u64,
+                    ))),
+                    _ => Ok(None),
+                }
+            }
+            name if self.peer_metrics.iter().any(|metric| metric == name) => {
+                Ok(Some((name.to_string(), value as u64)))
             }
-            "substrate_sub_libp2p_peers_count" => Ok(Some(("connected".to_string(), value as u64))),
             _ => Ok(None),
         }
     }
 
+    /// Polls `/metrics` until it answers, so a node that's still starting isn't
+    /// mistaken for one without peers. Gives up after the startup grace period
+    /// or as soon as the node exits.
+    async fn wait_for_metrics_server(&mut self) -> Result<(), BootnodeError> {
+        let deadline = Instant::now() + self.startup_grace;
+        loop {
+            if self.fetch_metrics().await.is_ok() {
+                return Ok(());
+            }
+            if let Ok(Some(status)) = self.process.try_wait() {
+                return Err(BootnodeError::SpawnFailed(format!(
+                    "node exited with {}",
+                    status
+                )));
+            }
+            if Instant::now() >= deadline {
+                return Err(BootnodeError::MetricsServerDown(
+                    self.startup_grace.as_secs(),
+                ));
+            }
+            sleep(METRICS_READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Prints a line of peer counts every `WATCH_INTERVAL`, until the node
+    /// exits or the caller stops polling.
+    async fn watch(&mut self) -> Result<()> {
+        self.wait_for_metrics_server().await?;
+        let count = |count: Option<u64>| count.map_or("-".to_string(), |count| count.to_string());
+        loop {
+            if let Ok(Some(status)) = self.process.try_wait() {
+                anyhow::bail!("Node exited with {}", status);
+            }
+            match self.check_discovered_peers().await {
+                Ok(metrics) => {
+                    let mut line = format!(
+                        "{:>5}s discovered={} in={} out={}",
+                        self.started_at.elapsed().as_secs(),
+                        metrics.peers,
+                        count(metrics.inbound_peers),
+                        count(metrics.outbound_peers)
+                    );
+                    for (metric, value) in &metrics.peer_counts {
+                        line.push_str(&format!(" {}={}", metric, value));
+                    }
+                    println!("{}", line);
+                }
+                Err(e) => warn!("{} Failed to read node metrics: {}", EMOJI_WARNING, e),
+            }
+            sleep(WATCH_INTERVAL).await;
+        }
+    }
+
     async fn bootnode_is_working(
         &mut self,
+        check: &mut NodeCheck,
         timeout: Duration,
-    ) -> Result<(u64, TestStatus, Option<String>)> {
-        sleep(Duration::from_secs(5)).await;
+        min_peers: u64,
+        mode: TestMode,
+    ) -> Result<()> {
+        if let Err(e) = self.wait_for_metrics_server().await {
+            warn!(
+                "{} Node for {}/{} not ready: {}",
+                EMOJI_WARNING, self.operator, self.network, e
+            );
+            check.finish(0, Some(e));
+            return Ok(());
+        }
         let end_time = Instant::now() + timeout;
         let mut consecutive_failures = 0;
-        const MAX_CONSECUTIVE_FAILURES: u32 = 3;
 
         while Instant::now() < end_time {
             match self.check_discovered_peers().await {
                 Ok(metrics) => {
                     consecutive_failures = 0;
+                    check.observe(&metrics, self.started_at.elapsed());
+                    let connected = metrics.peers > 0
+                        || metrics.inbound_peers.unwrap_or(0) > 0
+                        || metrics.outbound_peers.unwrap_or(0) > 0;
                     match metrics.status {
-                        MetricsStatus::Available if metrics.peers >= self.cli.min_peers => {
+                        _ if mode == TestMode::Connect && connected => {
+                            info!(
+                                "{} Bootnode accepted a connection for {}/{}",
+                                EMOJI_SUCCESS, self.operator, self.network
+                            );
+                            check.finish(metrics.peers, None);
+                            return Ok(());
+                        }
+                        MetricsStatus::Available if metrics.peers >= min_peers => {
                             info!(
                                 "{} Bootnode working for {}/{} - discovered {} peers",
                                 EMOJI_SUCCESS, self.operator, self.network, metrics.peers
                             );
-                            return Ok((metrics.peers, TestStatus::Success, None));
+                            check.finish(metrics.peers, None);
+                            return Ok(());
                         }
                         MetricsStatus::Available => {
                             sleep(Duration::from_secs(1)).await;
                         }
                         MetricsStatus::NoMetricFound => {
                             consecutive_failures += 1;
-                            if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                            if consecutive_failures >= self.max_metrics_failures {
                                 warn!(
                                     "{} No metrics found after {} consecutive attempts for {}/{}",
                                     EMOJI_WARNING,
-                                    MAX_CONSECUTIVE_FAILURES,
+                                    self.max_metrics_failures,
                                     self.operator,
                                     self.network
                                 );
-                                return Ok((0, TestStatus::NoMetricFound, None));
+                                check.finish(0, Some(BootnodeError::NoMetrics));
+                                return Ok(());
                             }
                             sleep(Duration::from_secs(1)).await;
                         }
@@ -333,41 +1420,232 @@ impl NodeProcess {
                 }
                 Err(e) => {
                     consecutive_failures += 1;
-                    if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    if consecutive_failures >= self.max_metrics_failures {
                         error!(
                             "{} Consistent errors checking peers for {}/{}: {}",
                             EMOJI_ERROR, self.operator, self.network, e
                         );
-                        return Ok((0, TestStatus::MetricsUnavailable, Some(e.to_string())));
+                        check.finish(0, Some(BootnodeError::MetricsUnreachable(e.to_string())));
+                        return Ok(());
                     }
                     sleep(Duration::from_secs(1)).await;
                 }
             }
         }
 
+        // report how far discovery got, "2 of 5" says more than nothing
+        let peers = check.peers;
         warn!(
-            "{} Timeout waiting for peer discovery for {}/{}",
-            EMOJI_WARNING, self.operator, self.network
+            "{} Timeout waiting for peer discovery for {}/{} - discovered {} of {} peers",
+            EMOJI_WARNING, self.operator, self.network, peers, min_peers
         );
-        Ok((0, TestStatus::Timeout, None))
+        check.finish(peers, Some(BootnodeError::Timeout));
+        Ok(())
     }
 }
 
+/// Runs a node with only `bootnode` and prints its peer counts every second
+/// until `shutdown`, for watching discovery evolve rather than stopping at
+/// `--min-peers`. The node is cleaned up either way.
+pub async fn watch_bootnode(
+    cli: &Cli,
+    network: &str,
+    bootnode: &str,
+    network_config: &NetworkConfig,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    let mut node = spawn_node(cli, "cli", network, bootnode, network_config).await?;
+    let watched = tokio::select! {
+        watched = node.watch() => watched,
+        _ = shutdown.cancelled() => Ok(()),
+    };
+    node.cleanup(false).await?;
+    watched
+}
+
+/// Tests a bootnode up to `--test-retries` times, returning the first passing
+/// result or the last failing one. Each attempt runs a fresh node.
+///
+/// `Err` is only returned for failures of the tester itself; a bootnode that
+/// doesn't work is reported through the result's `status`.
 pub async fn test_bootnode(
     cli: &Cli,
     operator: &str,
     network: &str,
     bootnode: &str,
-    command_id: &str,
+    network_config: &NetworkConfig,
+    shutdown: &CancellationToken,
+) -> Result<TestResult> {
+    let mut attempt = 1;
+    loop {
+        let result =
+            test_bootnode_once(cli, operator, network, bootnode, network_config, shutdown).await?;
+
+        // a malformed address fails the same way every time, and a deferred test
+        // already waited for memory
+        let retryable = !matches!(
+            result.status,
+            TestStatus::InvalidMultiaddr | TestStatus::Deferred
+        );
+        if result.valid || !retryable || attempt >= cli.test_retries || shutdown.is_cancelled() {
+            return Ok(result);
+        }
+
+        warn!(
+            "{} Attempt {}/{} failed for {}/{}, retrying",
+            EMOJI_WARNING, attempt, cli.test_retries, operator, network
+        );
+        attempt += 1;
+    }
+}
+
+async fn test_bootnode_once(
+    cli: &Cli,
+    operator: &str,
+    network: &str,
+    bootnode: &str,
+    network_config: &NetworkConfig,
+    shutdown: &CancellationToken,
 ) -> Result<TestResult> {
     let start_time = Instant::now();
+    let test_timeout = Duration::from_secs(network_config.timeout(cli));
+    let min_peers = network_config.min_peers(cli);
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
 
     info!(
         "{} Testing bootnode {} for {}/{}",
         EMOJI_LOADING, bootnode, operator, network
     );
 
-    let mut node = match spawn_node(cli, operator, network, bootnode, command_id).await {
+    let tester_binary_version = binary_version(node_binary(cli, &network_config.command_id)).await;
+    let failed = |e: BootnodeError| TestResult {
+        timestamp: timestamp.clone(),
+        test_duration_ms: start_time.elapsed().as_millis() as u64,
+        sync_mode: network_config.sync_mode(cli),
+        tester_binary_version: tester_binary_version.clone(),
+        ..TestResult::failed(operator, network, bootnode, e.status(), e.to_string())
+    };
+
+    let addr = match parse_multiaddr(bootnode) {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!(
+                "{} Invalid bootnode multiaddr for {}/{}: {}",
+                EMOJI_ERROR, operator, network, e
+            );
+            return Ok(failed(BootnodeError::ParseError(e.to_string())));
+        }
+    };
+
+    let resolve_start = Instant::now();
+    let resolved = match resolve_bootnode(&addr).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            error!(
+                "{} Failed to resolve bootnode for {}/{}: {}",
+                EMOJI_ERROR, operator, network, e
+            );
+            return Ok(failed(BootnodeError::DnsFailed(e.to_string())));
+        }
+    };
+    let dns_resolution = addr.host.is_dns().then(|| DnsResolution {
+        hostname: addr.host.lookup_name(),
+        duration_ms: resolve_start.elapsed().as_millis() as u64,
+    });
+
+    // udp transports can't be probed with a plain connect, leave them to the node
+    let mut ip = resolved.first().map(SocketAddr::ip);
+    let mut handshake_latency_ms = None;
+    let mut ip_families = None;
+    if addr.transport.is_tcp() {
+        // behind a proxy only the proxy's path to the bootnode is of interest,
+        // the per-family direct connects would test this host's instead
+        let connected = match &cli.proxy {
+            Some(proxy) if proxy.scheme() != "socks5" => Ok(addr.host.lookup_name()),
+            Some(_) => Ok(resolved[0].ip().to_string()),
+            None => {
+                let (families, connected) = connect_by_family(&resolved).await;
+                ip_families = Some(families);
+                connected.map(|connected| {
+                    ip = Some(connected.ip());
+                    connected.ip().to_string()
+                })
+            }
+        };
+        let handshake = match connected {
+            Ok(target) => {
+                match timeout(
+                    PREFLIGHT_TIMEOUT,
+                    handshake(&addr, &target, cli.proxy.as_ref()),
+                )
+                .await
+                {
+                    Ok(handshake) => handshake,
+                    Err(_) => Err(anyhow::anyhow!("handshake timed out")),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        match handshake {
+            Ok(latency) => handshake_latency_ms = Some(latency.as_millis() as u64),
+            Err(e) => {
+                error!(
+                    "{} Bootnode unreachable for {}/{}: {}",
+                    EMOJI_ERROR, operator, network, e
+                );
+                return Ok(TestResult {
+                    dns_resolution,
+                    ip,
+                    ip_families,
+                    ..failed(BootnodeError::Unreachable(e.to_string()))
+                });
+            }
+        }
+    }
+
+    // a parachain node without its relay chain can't find peers either way
+    let relay_rpc = relay_rpc(cli, network, network_config)?;
+    let mut relay_rpc_ip = None;
+    if let Some(relay_rpc) = &relay_rpc {
+        let family = network_config.relay_rpc_family;
+        match check_relay_rpc(relay_rpc, family, cli.proxy.as_ref()).await {
+            Ok(ip) => relay_rpc_ip = ip,
+            Err(e) => {
+                error!(
+                    "{} Relay chain RPC unavailable for {}/{}: {:#}",
+                    EMOJI_ERROR, operator, network, e
+                );
+                return Ok(TestResult {
+                    dns_resolution,
+                    ip,
+                    ip_families,
+                    handshake_latency_ms,
+                    relay_rpc: Some(relay_rpc.clone()),
+                    ..failed(BootnodeError::RelayRpcUnavailable(format!("{:#}", e)))
+                });
+            }
+        }
+    }
+
+    if let Some(min_free_mb) = cli.min_free_mem_mb {
+        if let Err(available_mb) = wait_for_free_memory(min_free_mb, shutdown).await {
+            warn!(
+                "{} Deferring {}/{}: {} MB of memory available, need {} MB",
+                EMOJI_WARNING, operator, network, available_mb, min_free_mb
+            );
+            return Ok(TestResult {
+                dns_resolution,
+                ip,
+                ip_families,
+                handshake_latency_ms,
+                relay_rpc,
+                relay_rpc_ip,
+                ..failed(BootnodeError::Deferred { available_mb })
+            });
+        }
+    }
+
+    let mut node = match spawn_node(cli, operator, network, bootnode, network_config).await {
         Ok(node) => node,
         Err(e) => {
             error!(
@@ -375,34 +1653,163 @@ pub async fn test_bootnode(
                 EMOJI_ERROR, operator, network, e
             );
             return Ok(TestResult {
-                id: operator.to_string(),
-                network: network.to_string(),
-                bootnode: bootnode.to_string(),
-                valid: false,
-                test_duration_ms: start_time.elapsed().as_millis() as u64,
-                discovered_peers: 0,
-                status: TestStatus::NodeStartupFailed,
-                error_details: Some(e.to_string()),
+                dns_resolution,
+                ip,
+                ip_families,
+                handshake_latency_ms,
+                relay_rpc,
+                relay_rpc_ip,
+                ..failed(BootnodeError::SpawnFailed(e.to_string()))
             });
         }
     };
 
-    let (discovered_peers, status, error_details) = node
-        .bootnode_is_working(Duration::from_secs(cli.timeout))
-        .await?;
+    // hard stop for the whole test, on top of the polling loop's own timeout,
+    // so a node whose metrics endpoint hangs can't stall the cycle
+    let budget = (test_timeout + network_config.startup_grace(cli) + NODE_DEADLINE_SLACK)
+        .saturating_sub(start_time.elapsed());
+    // outside the timed future, so the deadline path still knows what was seen
+    let mut check = NodeCheck::new();
+    let working = tokio::select! {
+        working = timeout(
+            budget,
+            node.bootnode_is_working(&mut check, test_timeout, min_peers, cli.mode),
+        ) => Some(working),
+        _ = shutdown.cancelled() => None,
+    };
+    let working = working.map(|working| match working {
+        Ok(working) => working.map(|()| check),
+        Err(_) => {
+            warn!(
+                "{} Node for {}/{} exceeded its deadline - discovered {} of {} peers",
+                EMOJI_WARNING, operator, network, check.peers, min_peers
+            );
+            check.finish(check.peers, Some(BootnodeError::Timeout));
+            Ok(check)
+        }
+    });
 
     let test_duration_ms = start_time.elapsed().as_millis() as u64;
 
-    node.cleanup().await?;
+    // the log lives in the data dir, so grab it before cleanup removes it
+    let mut working = working;
+    if let Some(Ok(check)) = &mut working {
+        if check.error.is_some() {
+            if let Some(line) = node.find_in_log(PEER_ID_MISMATCH_PATTERN) {
+                check.error = Some(BootnodeError::PeerIdMismatch(line));
+            }
+        }
+    }
+    let node_log = match &working {
+        Some(Ok(check)) if check.error.is_some() => node.tail_log(NODE_LOG_TAIL_LINES),
+        _ => None,
+    };
+    let usage = node.resource_usage();
+
+    let failed = match &working {
+        Some(Ok(check)) => check.error.is_some(),
+        Some(Err(_)) => true,
+        None => false,
+    };
+    node.cleanup(cli.keep_failed_data && failed).await?;
+
+    let Some(working) = working else {
+        anyhow::bail!("Test for {}/{} cancelled by shutdown", operator, network);
+    };
+    let check = working?;
 
     Ok(TestResult {
+        timestamp,
         id: operator.to_string(),
         network: network.to_string(),
         bootnode: bootnode.to_string(),
-        valid: discovered_peers >= cli.min_peers,
+        valid: match cli.mode {
+            TestMode::Full => check.peers >= min_peers,
+            TestMode::Connect => check.error.is_none(),
+        },
         test_duration_ms,
-        discovered_peers,
-        status,
-        error_details,
+        discovered_peers: check.peers,
+        status: check.status(),
+        error_details: check.error.as_ref().map(ToString::to_string),
+        dns_resolution,
+        ip,
+        ip_families,
+        handshake_latency_ms,
+        relay_rpc,
+        relay_rpc_ip,
+        sync_mode: network_config.sync_mode(cli),
+        tester_binary_version,
+        sync_progress: check.sync_progress,
+        inbound_peers: check.inbound_peers,
+        outbound_peers: check.outbound_peers,
+        peer_counts: (!check.peer_counts.is_empty()).then_some(check.peer_counts),
+        uptime_ratio: None,
+        consecutive_failures: None,
+        time_to_first_peer_ms: check.time_to_first_peer_ms,
+        node_rss_bytes: usage.map(|usage| usage.rss_bytes),
+        node_cpu_time_ms: usage.map(|usage| usage.cpu_time_ms),
+        node_log,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_nodes_of_one_operator_get_their_own_data_dir() {
+        init_port_pool(50000, &[]);
+        let (_, first_port) = acquire_ports().unwrap();
+        let (_, second_port) = acquire_ports().unwrap();
+
+        let data_dir = Path::new("/data");
+        assert_ne!(
+            node_data_dir(data_dir, "operator", "polkadot", first_port),
+            node_data_dir(data_dir, "operator", "polkadot", second_port)
+        );
+    }
+
+    #[test]
+    fn sweep_skips_data_dirs_of_live_nodes() {
+        let data_dir =
+            std::env::temp_dir().join(format!("bootyspector-sweep-{}", std::process::id()));
+        let live = node_data_dir(&data_dir, "operator", "polkadot", 50001);
+        let stale = node_data_dir(&data_dir, "operator", "polkadot", 50003);
+        std::fs::create_dir_all(&live).unwrap();
+        std::fs::create_dir_all(&stale).unwrap();
+        let lock = lock_data_dir(&live).unwrap();
+        // a dead owner's lock is released by the OS
+        drop(lock_data_dir(&stale).unwrap());
+
+        assert_eq!(remove_stale_data_dirs(&data_dir, false).unwrap(), 1);
+        assert!(live.exists());
+        assert!(!stale.exists());
+
+        drop(lock);
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn kept_data_dir_gets_a_name_of_its_own() {
+        let data_dir =
+            std::env::temp_dir().join(format!("bootyspector-keep-{}", std::process::id()));
+        let node_dir = node_data_dir(&data_dir, "operator", "polkadot", 30333);
+        std::fs::create_dir_all(&node_dir).unwrap();
+
+        let kept = quarantine_data_dir(&node_dir);
+        assert_ne!(kept, node_dir);
+        assert_eq!(kept.parent(), Some(data_dir.as_path()));
+        std::fs::rename(&node_dir, &kept).unwrap();
+
+        // the port's dir is free again; the kept one survives restarts while
+        // failed data is kept, and goes at the first startup without the flag
+        assert!(!node_dir.exists());
+        assert!(is_quarantined_data_dir(kept.file_name().unwrap()));
+        assert_eq!(remove_stale_data_dirs(&data_dir, true).unwrap(), 0);
+        assert!(kept.exists());
+        assert_eq!(remove_stale_data_dirs(&data_dir, false).unwrap(), 1);
+        assert!(!kept.exists());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -1,8 +1,95 @@
 // src/cli.rs
-use anyhow::Result;
-use clap::Parser;
-use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use anyhow::{Context, Result};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use url::Url;
+
+use crate::bootnode::{parse_node_version, MAX_PORT, MIN_PORT};
+
+pub const RELAY_PLACEHOLDER: &str = "{relay}";
+const DEFAULT_RELAY_RPC_TEMPLATE: &str = "wss://{relay}.dotters.network/";
+
+/// Peer count metrics of current substrate nodes: connected, syncing and
+/// kademlia routing table peers.
+const DEFAULT_PEER_METRICS: &str =
+    "substrate_sub_libp2p_peers_count,substrate_sync_peers,substrate_sub_libp2p_kbuckets_num_entries";
+
+/// Substrate metric names the node checks read, from the `[metric_names]`
+/// section of the TOML config. Defaults to the names of current substrate.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MetricNames {
+    /// peers the node discovered, what a check passes or fails on
+    pub discovered: String,
+    /// block height, with `best` and `sync_target` in its `status` label
+    pub block_height: String,
+    /// connections opened, by `direction` label
+    pub connections_opened: String,
+    /// connections closed, by `direction` label
+    pub connections_closed: String,
+}
+
+impl Default for MetricNames {
+    fn default() -> Self {
+        Self {
+            discovered: "substrate_sub_libp2p_peerset_num_discovered".to_string(),
+            block_height: "substrate_block_height".to_string(),
+            connections_opened: "substrate_sub_libp2p_connections_opened_total".to_string(),
+            connections_closed: "substrate_sub_libp2p_connections_closed_total".to_string(),
+        }
+    }
+}
+
+impl MetricNames {
+    /// The `[metric_names]` entry that `peer_metric` would be read as instead
+    /// of a peer count, if any.
+    fn shadowing(&self, peer_metric: &str) -> Option<&'static str> {
+        [
+            ("discovered", &self.discovered),
+            ("block_height", &self.block_height),
+            ("connections_opened", &self.connections_opened),
+            ("connections_closed", &self.connections_closed),
+        ]
+        .into_iter()
+        .find(|(_, name)| *name == peer_metric)
+        .map(|(entry, _)| entry)
+    }
+}
+
+/// Node profile used by networks that don't name one.
+pub const DEFAULT_NODE_PROFILE: &str = "default";
+/// Arguments of the built-in `default` profile, for current substrate nodes.
+/// See [`crate::bootnode::NodePlan::command`] for the placeholders.
+const DEFAULT_NODE_PROFILE_ARGS: &[&str] = &[
+    "--no-hardware-benchmarks",
+    "--no-mdns",
+    "--prometheus-external",
+    "--prometheus-port={prometheus_port}",
+    "--port={p2p_port}",
+    "--base-path={data_dir}",
+    "--chain={chain_spec}",
+    "--bootnodes={bootnode}",
+    "--relay-chain-rpc-urls={relay_rpc}",
+    "--sync={sync_mode}",
+];
+
+fn validate_relay_rpc_template(template: &str) -> Result<()> {
+    if !template.contains(RELAY_PLACEHOLDER) {
+        anyhow::bail!(
+            "Relay RPC template {:?} is missing the {} placeholder",
+            template,
+            RELAY_PLACEHOLDER
+        );
+    }
+    Ok(())
+}
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -12,128 +99,895 @@ use std::{fs, path::PathBuf};
 )]
 pub struct Cli {
     /// path to the polkadot binary
-    #[arg(long, default_value = "/usr/local/bin/polkadot")]
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_POLKADOT_BINARY",
+        default_value = "/usr/local/bin/polkadot"
+    )]
     pub polkadot_binary: PathBuf,
 
     /// path to the parachain binary
-    #[arg(long, default_value = "/usr/local/bin/polkadot-parachain")]
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_PARACHAIN_BINARY",
+        default_value = "/usr/local/bin/polkadot-parachain"
+    )]
     pub parachain_binary: PathBuf,
 
     /// path to the encointer binary
-    #[arg(long, default_value = "/usr/local/bin/encointer")]
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_ENCOINTER_BINARY",
+        default_value = "/usr/local/bin/encointer"
+    )]
     pub encointer_binary: PathBuf,
 
-    #[arg(long, default_value = "/tmp/bootnode_tests")]
+    /// warn at startup when the polkadot or parachain binary is older than this version, e.g. 1.17.0
+    #[arg(long)]
+    pub min_node_version: Option<String>,
+
+    /// abort instead of warning when a binary is older than --min-node-version or its version is unknown
+    #[arg(long)]
+    pub strict_version: bool,
+
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_OUTPUT_DIR",
+        default_value = "/tmp/bootnode_tests"
+    )]
     pub output_dir: PathBuf,
 
-    #[arg(long, default_value = "/tmp/bootnode_data")]
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_DATA_DIR",
+        default_value = "/tmp/bootnode_data"
+    )]
     pub data_dir: PathBuf,
 
+    /// wait for this much available memory before starting a node, deferring the test if it doesn't free up
+    #[arg(long)]
+    pub min_free_mem_mb: Option<u64>,
+
+    /// don't start new nodes while the data dir holds more than this many bytes
+    #[arg(long)]
+    pub max_data_dir_bytes: Option<u64>,
+
     /// path to the chain spec directory
-    #[arg(long, default_value = "./chain-spec")]
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_CHAIN_SPEC_DIR",
+        default_value = "./chain-spec"
+    )]
     pub chain_spec_dir: PathBuf,
 
     /// maximum number of concurrent tests
-    #[arg(long, default_value = "1")]
+    #[arg(long, env = "BOOTSPECTOR_MAX_CONCURRENT", default_value = "1")]
     pub max_concurrent: usize,
 
+    /// maximum number of concurrent relay chain node tests, within --max-concurrent
+    #[arg(long, env = "BOOTSPECTOR_MAX_CONCURRENT_RELAY")]
+    pub max_concurrent_relay: Option<usize>,
+
+    /// maximum number of concurrent parachain node tests, within --max-concurrent
+    #[arg(long, env = "BOOTSPECTOR_MAX_CONCURRENT_PARACHAIN")]
+    pub max_concurrent_parachain: Option<usize>,
+
+    /// maximum number of concurrent encointer node tests, within --max-concurrent
+    #[arg(long, env = "BOOTSPECTOR_MAX_CONCURRENT_ENCOINTER")]
+    pub max_concurrent_encointer: Option<usize>,
+
+    /// maximum number of concurrent tests of a single operator's bootnodes, within --max-concurrent
+    #[arg(long)]
+    pub max_concurrent_per_operator: Option<usize>,
+
     /// minimum number of peers to pass
-    #[arg(long, default_value = "2")]
+    #[arg(long, env = "BOOTSPECTOR_MIN_PEERS", default_value = "2")]
     pub min_peers: u64,
 
+    /// full waits for --min-peers discovered peers, connect passes as soon as the node has a peer or connection
+    #[arg(long, value_enum, default_value = "full")]
+    pub mode: TestMode,
+
     /// test interval in seconds
-    #[arg(long, default_value = "3600")]
+    #[arg(long, env = "BOOTSPECTOR_INTERVAL", default_value = "3600")]
     pub interval: u64,
 
-    #[arg(long, default_value = "49615")]
+    /// add a random delay of up to this many seconds before each next cycle
+    #[arg(long, default_value = "0")]
+    pub cycle_jitter_secs: u64,
+
+    #[arg(long, env = "BOOTSPECTOR_BASE_PORT", default_value = "49615")]
     pub base_port: u16,
 
-    #[arg(long, default_value = "9615")]
+    #[arg(long, env = "BOOTSPECTOR_PROMETHEUS_PORT", default_value = "9615")]
     pub prometheus_port: u16,
 
     /// test ttl in seconds
-    #[arg(long, default_value = "30")]
+    #[arg(long, env = "BOOTSPECTOR_TIMEOUT", default_value = "30")]
     pub timeout: u64,
 
-    /// path to the bootnodes config file
-    #[arg(long, default_value = "bootnodes.json")]
+    /// seconds to wait for a node's metrics server to come up before checking its peers
+    #[arg(long, default_value = "30")]
+    pub startup_grace_secs: u64,
+
+    /// attempts to scrape node metrics per poll
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
+    pub metrics_retries: u32,
+
+    /// node metrics to report as peer counts, comma separated
+    #[arg(long, value_delimiter = ',', default_value = DEFAULT_PEER_METRICS)]
+    pub peer_metrics: Vec<String>,
+
+    /// backoff before the second metrics scrape attempt in ms, doubled per attempt
+    #[arg(long, default_value = "100")]
+    pub metrics_initial_backoff_ms: u64,
+
+    /// polls in a row without usable node metrics before a test gives up on the node
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_metrics_failures: u32,
+
+    /// attempts per bootnode before it counts as failed
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub test_retries: u32,
+
+    /// path or http(s) URL of the bootnodes config, reloaded between cycles when it changes
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_BOOTNODES_CONFIG",
+        default_value = "bootnodes.json"
+    )]
     pub bootnodes_config: PathBuf,
 
-    #[arg(long)]
+    #[arg(long, env = "BOOTSPECTOR_CONFIG")]
     pub config: Option<PathBuf>,
 
+    /// only test these networks, comma separated; empty tests all
+    #[arg(long, env = "BOOTSPECTOR_NETWORKS", value_delimiter = ',')]
+    pub networks: Vec<String>,
+
+    /// only test these operators, comma separated; empty tests all
+    #[arg(long, env = "BOOTSPECTOR_OPERATORS", value_delimiter = ',')]
+    pub operators: Vec<String>,
+
+    /// only warn about missing chain specs at startup instead of exiting
+    #[arg(long)]
+    pub allow_missing_chain_specs: bool,
+
+    /// relay chain rpc url for parachain nodes, {relay} is replaced by the relay name
+    #[arg(long, default_value = DEFAULT_RELAY_RPC_TEMPLATE)]
+    pub relay_rpc_template: String,
+
+    /// extra arguments passed verbatim to every node, e.g. --extra-node-args="--log sync=debug"
+    #[arg(long, allow_hyphen_values = true, value_delimiter = ' ')]
+    pub extra_node_args: Vec<String>,
+
+    /// sync strategy of the test nodes, the node's own default if unset
+    #[arg(long, value_enum)]
+    pub sync_mode: Option<SyncMode>,
+
+    /// socks5, socks5h or http proxy for the pre-flight bootnode and relay rpc checks and the remote bootnodes config
+    #[arg(long, env = "BOOTSPECTOR_PROXY", hide_env_values = true)]
+    pub proxy: Option<Url>,
+
+    /// leave the data dir of nodes whose test failed for inspection; they are removed at the first startup without this flag
+    #[arg(long)]
+    pub keep_failed_data: bool,
+
+    /// write node stderr to a log in its data dir, attach its tail to failed results and detect peer id mismatches
+    #[arg(long)]
+    pub capture_logs: bool,
+
+    /// window in hours for the rolling uptime ratio
+    #[arg(long, default_value = "168")]
+    pub uptime_window_hours: u64,
+
+    /// keep the last N results per operator/network in results.json instead of only the latest
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub keep_history: Option<u64>,
+
+    /// MaxMind country (or city) database to label bootnodes with their country
+    #[arg(long)]
+    pub geoip_db: Option<PathBuf>,
+
+    /// MaxMind ASN database to label bootnodes with their autonomous system
+    #[arg(long)]
+    pub geoip_asn_db: Option<PathBuf>,
+
+    /// SQLite database to append every test result to
+    #[arg(long)]
+    pub sqlite: Option<PathBuf>,
+
+    /// file to append every test result to as a JSON line
+    #[arg(long)]
+    pub jsonl_output: Option<PathBuf>,
+
+    /// file to write the latest result of every bootnode to as CSV after each cycle
+    #[arg(long)]
+    pub csv_output: Option<PathBuf>,
+
+    /// bearer token required to scrape /metrics, open when unset
+    #[arg(long, env = "BOOTSPECTOR_METRICS_TOKEN", hide_env_values = true)]
+    pub metrics_token: Option<String>,
+
+    /// Prometheus Pushgateway to push metrics to after every cycle
+    #[arg(long, env = "BOOTSPECTOR_PUSHGATEWAY")]
+    pub pushgateway: Option<Url>,
+
+    /// job label for metrics pushed to the Pushgateway
+    #[arg(long, default_value = "bootyspector")]
+    pub pushgateway_job: String,
+
+    /// URL to POST a JSON alert to when a bootnode starts failing
+    #[arg(long, env = "BOOTSPECTOR_ALERT_WEBHOOK", hide_env_values = true)]
+    pub alert_webhook: Option<Url>,
+
+    /// consecutive failed checks before a bootnode is alerted on
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    pub alert_after_failures: u64,
+
+    /// Slack incoming webhook to post a summary of new failures and recoveries to after each cycle
+    #[arg(long, env = "BOOTSPECTOR_SLACK_WEBHOOK", hide_env_values = true)]
+    pub slack_webhook: Option<Url>,
+
+    /// PagerDuty Events v2 routing key; failures of networks marked critical open an incident
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_PAGERDUTY_ROUTING_KEY",
+        hide_env_values = true
+    )]
+    pub pagerduty_routing_key: Option<String>,
+
+    /// Matrix homeserver to post alerts to, e.g. https://matrix.org
+    #[arg(long, env = "BOOTSPECTOR_MATRIX_HOMESERVER", requires_all = ["matrix_token", "matrix_room"])]
+    pub matrix_homeserver: Option<Url>,
+
+    /// Matrix access token of the alerting account
+    #[arg(
+        long,
+        env = "BOOTSPECTOR_MATRIX_TOKEN",
+        hide_env_values = true,
+        requires = "matrix_homeserver"
+    )]
+    pub matrix_token: Option<String>,
+
+    /// Matrix room id to post alerts to, e.g. !abcdef:matrix.org
+    #[arg(long, env = "BOOTSPECTOR_MATRIX_ROOM", requires = "matrix_homeserver")]
+    pub matrix_room: Option<String>,
+
+    /// report results of bootnodes sharing a peer id per transport
+    #[arg(long)]
+    pub group_by_peer_id: bool,
+
+    /// test a bootnode listed several times in a network (also under different operators) only once, reusing its result
+    #[arg(long)]
+    pub dedupe: bool,
+
+    /// print the node command for every bootnode that would be tested and exit
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// test the failures of each cycle once more right away before counting them as failed
+    #[arg(long)]
+    pub recheck_failures: bool,
+
+    /// test only this bootnode multiaddr without a bootnodes config, print the result and exit
+    #[arg(long, requires = "network")]
+    pub bootnode: Option<String>,
+
+    /// network of --bootnode, which picks its chain spec
+    #[arg(long)]
+    pub network: Option<String>,
+
+    /// with --bootnode, keep the node running and print its peer counts every second until Ctrl-C
+    #[arg(long, requires = "bootnode")]
+    pub watch: bool,
+
+    /// commandId of the --bootnode network: polkadot, parachain or encointer
+    #[arg(long, default_value = "polkadot")]
+    pub command_id: String,
+
+    /// run a single test cycle and exit, nonzero if any bootnode failed
+    #[arg(long)]
+    pub once: bool,
+
+    /// run this many test cycles and exit, nonzero if any bootnode failed in any of them
+    #[arg(long, conflicts_with = "once", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_cycles: Option<u64>,
+
+    /// format of the report printed when the run ends
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub report_format: ReportFormat,
+
     #[arg(long)]
     pub debug: bool,
+
+    /// log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// node argument templates by profile name, from the `[profiles]` section
+    /// of the TOML config
+    #[arg(skip)]
+    pub node_profiles: BTreeMap<String, Vec<String>>,
+
+    /// metric names from the `[metric_names]` section of the TOML config
+    #[arg(skip)]
+    pub metric_names: MetricNames,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// What a test waits for before it passes.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestMode {
+    Full,
+    Connect,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// Values of the node's `--sync` argument.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    Full,
+    Fast,
+    Warp,
+}
+
+impl SyncMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncMode::Full => "full",
+            SyncMode::Fast => "fast",
+            SyncMode::Warp => "warp",
+        }
+    }
+}
+
+/// IP address family, "v4" or "v6" like the `ip_family` metric label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub fn matches(&self, ip: &IpAddr) -> bool {
+        match self {
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TomlConfig {
     pub polkadot_binary: Option<PathBuf>,
     pub parachain_binary: Option<PathBuf>,
+    pub encointer_binary: Option<PathBuf>,
+    pub min_node_version: Option<String>,
+    pub strict_version: Option<bool>,
     pub output_dir: Option<PathBuf>,
     pub data_dir: Option<PathBuf>,
+    pub min_free_mem_mb: Option<u64>,
+    pub max_data_dir_bytes: Option<u64>,
     pub chain_spec_dir: Option<PathBuf>,
     pub max_concurrent: Option<usize>,
+    pub max_concurrent_relay: Option<usize>,
+    pub max_concurrent_parachain: Option<usize>,
+    pub max_concurrent_encointer: Option<usize>,
+    pub max_concurrent_per_operator: Option<usize>,
+    pub min_peers: Option<u64>,
+    pub mode: Option<TestMode>,
+    pub interval: Option<u64>,
+    pub cycle_jitter_secs: Option<u64>,
     pub base_port: Option<u16>,
+    pub prometheus_port: Option<u16>,
     pub timeout: Option<u64>,
+    pub test_retries: Option<u32>,
+    pub startup_grace_secs: Option<u64>,
+    pub metrics_retries: Option<u32>,
+    pub metrics_initial_backoff_ms: Option<u64>,
+    pub max_metrics_failures: Option<u32>,
     pub bootnodes_config: Option<PathBuf>,
+    pub networks: Option<Vec<String>>,
+    pub operators: Option<Vec<String>>,
+    pub peer_metrics: Option<Vec<String>>,
+    pub allow_missing_chain_specs: Option<bool>,
+    pub relay_rpc_template: Option<String>,
+    pub extra_node_args: Option<Vec<String>>,
+    pub sync_mode: Option<SyncMode>,
+    pub proxy: Option<Url>,
+    pub capture_logs: Option<bool>,
+    pub keep_failed_data: Option<bool>,
+    pub group_by_peer_id: Option<bool>,
+    pub dedupe: Option<bool>,
+    pub recheck_failures: Option<bool>,
+    pub uptime_window_hours: Option<u64>,
+    pub keep_history: Option<u64>,
+    pub max_cycles: Option<u64>,
+    pub once: Option<bool>,
+    pub dry_run: Option<bool>,
+    pub command_id: Option<String>,
+    pub geoip_db: Option<PathBuf>,
+    pub geoip_asn_db: Option<PathBuf>,
+    pub sqlite: Option<PathBuf>,
+    pub jsonl_output: Option<PathBuf>,
+    pub csv_output: Option<PathBuf>,
+    pub metrics_token: Option<String>,
+    pub pushgateway: Option<Url>,
+    pub pushgateway_job: Option<String>,
+    pub alert_webhook: Option<Url>,
+    pub alert_after_failures: Option<u64>,
+    pub slack_webhook: Option<Url>,
+    pub pagerduty_routing_key: Option<String>,
+    pub matrix_homeserver: Option<Url>,
+    pub matrix_token: Option<String>,
+    pub matrix_room: Option<String>,
+    pub debug: Option<bool>,
+    pub log_format: Option<LogFormat>,
+    pub report_format: Option<ReportFormat>,
+    pub profiles: Option<BTreeMap<String, Vec<String>>>,
+    pub metric_names: Option<MetricNames>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct BootnodesConfig {
     #[serde(flatten)]
     pub networks: std::collections::HashMap<String, NetworkConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct NetworkConfig {
     #[serde(rename = "commandId")]
     pub command_id: String,
     pub members: std::collections::HashMap<String, Vec<String>>,
+    /// overrides `--timeout` for this network
+    pub timeout: Option<u64>,
+    /// overrides `--min-peers` for this network
+    #[serde(rename = "minPeers", alias = "min_peers")]
+    pub min_peers: Option<u64>,
+    /// overrides `--startup-grace-secs` for this network
+    #[serde(rename = "startupGraceSecs", alias = "startup_grace_secs")]
+    pub startup_grace_secs: Option<u64>,
+    /// passed verbatim to nodes of this network, after `--extra-node-args`
+    #[serde(default, rename = "extraArgs", alias = "extra_args")]
+    pub extra_args: Vec<String>,
+    /// overrides `--relay-rpc-template` for this network
+    #[serde(rename = "relayRpcTemplate", alias = "relay_rpc_template")]
+    pub relay_rpc_template: Option<String>,
+    /// relay RPC templates per address family, for endpoints that only serve one
+    #[serde(default, rename = "relayRpcTemplates", alias = "relay_rpc_templates")]
+    pub relay_rpc_templates: BTreeMap<IpFamily, String>,
+    /// pins the relay RPC to one address family: its `relayRpcTemplates` entry
+    /// is used if there is one, and the pre-flight check only connects over it
+    #[serde(rename = "relayRpcFamily", alias = "relay_rpc_family")]
+    pub relay_rpc_family: Option<IpFamily>,
+    /// chain spec to use instead of `{chain_spec_dir}/{network}.json`,
+    /// relative to `--chain-spec-dir` unless absolute
+    #[serde(rename = "chainSpec", alias = "chain_spec")]
+    pub chain_spec: Option<PathBuf>,
+    /// page through `--pagerduty-routing-key` when a bootnode of this network fails
+    #[serde(default)]
+    pub critical: bool,
+    /// overrides `--sync-mode` for this network
+    #[serde(rename = "syncMode", alias = "sync_mode")]
+    pub sync_mode: Option<SyncMode>,
+    /// node profile whose arguments this network's nodes start with
+    pub profile: Option<String>,
 }
 
-impl Cli {
-    pub fn merge_with_toml(&mut self, config: TomlConfig) {
-        if let Some(v) = config.polkadot_binary {
-            self.polkadot_binary = v;
-        }
-        if let Some(v) = config.parachain_binary {
-            self.parachain_binary = v;
+impl BootnodesConfig {
+    /// Reads a JSON or YAML config, picked by file extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).context("Failed to open bootnodes config")?;
+        Self::parse(&content, path)
+    }
+
+    /// Parses `content` in the format implied by the extension of `path`.
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(content)
+                .with_context(|| format!("Failed to parse bootnodes config {:?}", path)),
+            Some("yaml" | "yml") => serde_yaml::from_str(content)
+                .with_context(|| format!("Failed to parse bootnodes config {:?}", path)),
+            _ => anyhow::bail!(
+                "Unrecognized bootnodes config format {:?}, expected .json, .yaml or .yml",
+                path
+            ),
         }
-        if let Some(v) = config.output_dir {
-            self.output_dir = v;
+    }
+
+    /// Chain spec paths that are referenced but don't exist.
+    pub fn missing_chain_specs(&self, chain_spec_dir: &Path) -> Vec<PathBuf> {
+        let mut missing: Vec<PathBuf> = self
+            .networks
+            .iter()
+            .map(|(network, config)| config.chain_spec(chain_spec_dir, network))
+            .filter(|path| !path.exists())
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (network, config) in &self.networks {
+            for template in config
+                .relay_rpc_template
+                .iter()
+                .chain(config.relay_rpc_templates.values())
+            {
+                validate_relay_rpc_template(template)
+                    .with_context(|| format!("Invalid config for network {}", network))?;
+            }
         }
-        if let Some(v) = config.data_dir {
-            self.data_dir = v;
+        Ok(())
+    }
+}
+
+impl NetworkConfig {
+    pub fn chain_spec(&self, chain_spec_dir: &Path, network: &str) -> PathBuf {
+        match &self.chain_spec {
+            Some(path) => chain_spec_dir.join(path),
+            None => chain_spec_dir.join(format!("{}.json", network)),
         }
-        if let Some(v) = config.chain_spec_dir {
-            self.chain_spec_dir = v;
+    }
+
+    pub fn timeout(&self, cli: &Cli) -> u64 {
+        self.timeout.unwrap_or(cli.timeout)
+    }
+
+    pub fn min_peers(&self, cli: &Cli) -> u64 {
+        self.min_peers.unwrap_or(cli.min_peers)
+    }
+
+    pub fn startup_grace(&self, cli: &Cli) -> Duration {
+        Duration::from_secs(self.startup_grace_secs.unwrap_or(cli.startup_grace_secs))
+    }
+
+    pub fn sync_mode(&self, cli: &Cli) -> Option<SyncMode> {
+        self.sync_mode.or(cli.sync_mode)
+    }
+
+    pub fn relay_rpc_url(&self, cli: &Cli, relay: &str) -> String {
+        self.relay_rpc_family
+            .and_then(|family| self.relay_rpc_templates.get(&family))
+            .or(self.relay_rpc_template.as_ref())
+            .unwrap_or(&cli.relay_rpc_template)
+            .replace(RELAY_PLACEHOLDER, relay)
+    }
+}
+
+impl Cli {
+    pub fn tests_network(&self, network: &str) -> bool {
+        self.networks.is_empty() || self.networks.iter().any(|n| n == network)
+    }
+
+    pub fn tests_operator(&self, operator: &str) -> bool {
+        self.operators.is_empty() || self.operators.iter().any(|o| o == operator)
+    }
+
+    /// Fills in values from the TOML config that weren't given on the command
+    /// line or in the environment, so precedence is CLI > env > TOML > default.
+    pub fn merge_with_toml(&mut self, config: TomlConfig, matches: &ArgMatches) {
+        let from_toml = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+
+        macro_rules! merge {
+            ($($field:ident),+ $(,)?) => {$(
+                if let Some(v) = config.$field {
+                    if from_toml(stringify!($field)) {
+                        self.$field = v;
+                    }
+                }
+            )+};
         }
-        if let Some(v) = config.max_concurrent {
-            self.max_concurrent = v;
+        macro_rules! merge_opt {
+            ($($field:ident),+ $(,)?) => {$(
+                if let Some(v) = config.$field {
+                    if from_toml(stringify!($field)) {
+                        self.$field = Some(v);
+                    }
+                }
+            )+};
         }
-        if let Some(v) = config.base_port {
-            self.base_port = v;
+
+        merge!(
+            polkadot_binary,
+            parachain_binary,
+            encointer_binary,
+            strict_version,
+            output_dir,
+            data_dir,
+            chain_spec_dir,
+            max_concurrent,
+            min_peers,
+            mode,
+            interval,
+            cycle_jitter_secs,
+            base_port,
+            prometheus_port,
+            timeout,
+            test_retries,
+            startup_grace_secs,
+            metrics_retries,
+            metrics_initial_backoff_ms,
+            max_metrics_failures,
+            peer_metrics,
+            bootnodes_config,
+            networks,
+            operators,
+            allow_missing_chain_specs,
+            relay_rpc_template,
+            extra_node_args,
+            capture_logs,
+            keep_failed_data,
+            group_by_peer_id,
+            dedupe,
+            recheck_failures,
+            once,
+            dry_run,
+            command_id,
+            uptime_window_hours,
+            debug,
+            log_format,
+            report_format,
+            pushgateway_job,
+            alert_after_failures,
+        );
+        merge_opt!(
+            max_concurrent_relay,
+            max_concurrent_parachain,
+            max_concurrent_encointer,
+            max_concurrent_per_operator,
+            min_node_version,
+            min_free_mem_mb,
+            max_data_dir_bytes,
+            sync_mode,
+            proxy,
+            keep_history,
+            max_cycles,
+            geoip_db,
+            geoip_asn_db,
+            sqlite,
+            jsonl_output,
+            csv_output,
+            metrics_token,
+            pushgateway,
+            alert_webhook,
+            slack_webhook,
+            pagerduty_routing_key,
+            matrix_homeserver,
+            matrix_token,
+            matrix_room,
+        );
+        if let Some(profiles) = config.profiles {
+            self.node_profiles = profiles;
         }
-        if let Some(v) = config.timeout {
-            self.timeout = v;
+        if let Some(metric_names) = config.metric_names {
+            self.metric_names = metric_names;
         }
-        if let Some(v) = config.bootnodes_config {
-            self.bootnodes_config = v;
+    }
+
+    /// Argument template of a node profile. The built-in `default` profile can
+    /// be overridden in the config like any other.
+    pub fn node_profile(&self, name: &str) -> Result<Vec<String>> {
+        match self.node_profiles.get(name) {
+            Some(args) => Ok(args.clone()),
+            None if name == DEFAULT_NODE_PROFILE => Ok(DEFAULT_NODE_PROFILE_ARGS
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect()),
+            None => anyhow::bail!("Unknown node profile {:?}", name),
         }
     }
 
     pub fn load() -> Result<Self> {
-        let mut cli = Self::parse();
+        Self::from_matches(&Self::command().get_matches())
+    }
 
-        if let Some(config_path) = &cli.config {
-            if let Ok(config_str) = fs::read_to_string(config_path) {
-                if let Ok(toml_config) = toml::from_str::<TomlConfig>(&config_str) {
-                    cli.merge_with_toml(toml_config);
-                }
+    /// The options of `matches` merged with the config file, validated.
+    fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let mut cli = Self::from_arg_matches(matches)?;
+
+        // a config that can't be used is fatal: silently dropping it could
+        // e.g. leave /metrics open without its token
+        if let Some(config_path) = cli.config.clone() {
+            let config_str = fs::read_to_string(&config_path)
+                .with_context(|| format!("Failed to read config {:?}", config_path))?;
+            let toml_config = toml::from_str::<TomlConfig>(&config_str)
+                .with_context(|| format!("Failed to parse config {:?}", config_path))?;
+            cli.merge_with_toml(toml_config, matches);
+        }
+
+        validate_relay_rpc_template(&cli.relay_rpc_template)?;
+        // every test takes two ports from base_port upwards
+        if !(MIN_PORT..MAX_PORT).contains(&cli.base_port) {
+            anyhow::bail!(
+                "base_port {} must be between {} and {}",
+                cli.base_port,
+                MIN_PORT,
+                MAX_PORT - 1
+            );
+        }
+        if cli.base_port == cli.prometheus_port {
+            anyhow::bail!(
+                "base_port and prometheus_port are both {}; the metrics server needs its own port",
+                cli.base_port
+            );
+        }
+        // clap enforces these for flags, but TOML values bypass it
+        if let Some(version) = &cli.min_node_version {
+            if parse_node_version(version).is_none() {
+                anyhow::bail!(
+                    "Invalid min_node_version {:?}, expected e.g. 1.17.0",
+                    version
+                );
+            }
+        }
+        if cli.strict_version && cli.min_node_version.is_none() {
+            anyhow::bail!("strict_version needs min_node_version");
+        }
+        if cli.keep_history == Some(0) {
+            anyhow::bail!("keep_history must be at least 1");
+        }
+        if cli.max_cycles == Some(0) {
+            anyhow::bail!("max_cycles must be at least 1");
+        }
+        if cli.test_retries == 0 {
+            anyhow::bail!("test_retries must be at least 1");
+        }
+        if cli.metrics_retries == 0 {
+            anyhow::bail!("metrics_retries must be at least 1");
+        }
+        if cli.max_metrics_failures == 0 {
+            anyhow::bail!("max_metrics_failures must be at least 1");
+        }
+        // such a metric is parsed as the [metric_names] entry, never as a peer count
+        for metric in &cli.peer_metrics {
+            if let Some(entry) = cli.metric_names.shadowing(metric) {
+                anyhow::bail!(
+                    "peer_metrics entry {:?} is also metric_names.{}; it can only be one of them",
+                    metric,
+                    entry
+                );
+            }
+        }
+        if [
+            cli.max_concurrent_relay,
+            cli.max_concurrent_parachain,
+            cli.max_concurrent_encointer,
+            cli.max_concurrent_per_operator,
+        ]
+        .contains(&Some(0))
+        {
+            anyhow::bail!(
+                "max_concurrent_relay/parachain/encointer/per_operator must be at least 1"
+            );
+        }
+        if let Some(proxy) = &cli.proxy {
+            if !matches!(proxy.scheme(), "socks5" | "socks5h" | "http") {
+                anyhow::bail!("proxy must be a socks5://, socks5h:// or http:// URL");
+            }
+            if proxy.host_str().is_none() {
+                anyhow::bail!("proxy URL has no host");
             }
+            if proxy.scheme() == "http" && !proxy.username().is_empty() {
+                anyhow::bail!("proxy credentials are only supported for socks5 proxies");
+            }
+        }
+        let matrix = [
+            cli.matrix_homeserver.is_some(),
+            cli.matrix_token.is_some(),
+            cli.matrix_room.is_some(),
+        ];
+        if matrix.contains(&true) && matrix.contains(&false) {
+            anyhow::bail!("matrix_homeserver, matrix_token and matrix_room must be set together");
         }
 
         Ok(cli)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(args: &[&str], toml: &str) -> Cli {
+        let matches = Cli::command()
+            .get_matches_from(std::iter::once("bootyspector").chain(args.iter().copied()));
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        cli.merge_with_toml(toml::from_str(toml).unwrap(), &matches);
+        cli
+    }
+
+    fn loaded(args: &[&str]) -> Result<Cli> {
+        Cli::from_matches(
+            &Cli::command()
+                .get_matches_from(std::iter::once("bootyspector").chain(args.iter().copied())),
+        )
+    }
+
+    #[test]
+    fn base_port_must_be_in_the_ephemeral_range() {
+        assert!(loaded(&["--base-port", "9700"]).is_err());
+        assert_eq!(loaded(&[]).unwrap().base_port, 49615);
+    }
+
+    #[test]
+    fn unusable_config_file_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("bootyspector-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, content) in [
+            ("typo.toml", "metrics_tokn = \"secret\""),
+            ("type.toml", "prometheus_port = \"9615\""),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            assert!(
+                loaded(&["--config", path.to_str().unwrap()]).is_err(),
+                "{}",
+                name
+            );
+        }
+        assert!(loaded(&["--config", dir.join("missing.toml").to_str().unwrap()]).is_err());
+
+        let path = dir.join("ok.toml");
+        fs::write(&path, "metrics_token = \"secret\"").unwrap();
+        let cli = loaded(&["--config", path.to_str().unwrap()]).unwrap();
+        assert_eq!(cli.metrics_token.as_deref(), Some("secret"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn explicit_flag_beats_toml() {
+        let cli = merged(&["--max-concurrent", "4"], "max_concurrent = 8");
+        assert_eq!(cli.max_concurrent, 4);
+    }
+
+    #[test]
+    fn toml_overrides_default() {
+        let cli = merged(&[], "max_concurrent = 8\nmin_peers = 5");
+        assert_eq!(cli.max_concurrent, 8);
+        assert_eq!(cli.min_peers, 5);
+    }
+
+    #[test]
+    fn absent_toml_key_keeps_default() {
+        let cli = merged(&[], "min_peers = 5");
+        assert_eq!(cli.max_concurrent, 1);
+        assert_eq!(cli.timeout, 30);
+        assert_eq!(cli.metrics_token, None);
+    }
+
+    #[test]
+    fn metric_names_shadow_peer_metrics_of_the_same_name() {
+        let names = MetricNames::default();
+        assert_eq!(
+            names.shadowing("substrate_block_height"),
+            Some("block_height")
+        );
+        for metric in DEFAULT_PEER_METRICS.split(',') {
+            assert_eq!(names.shadowing(metric), None);
+        }
+    }
+}
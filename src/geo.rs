@@ -0,0 +1,57 @@
+// src/geo.rs
+use anyhow::{Context, Result};
+use maxminddb::{geoip2, Reader};
+use std::{net::IpAddr, path::Path};
+
+use crate::cli::Cli;
+
+const UNKNOWN: &str = "unknown";
+
+/// Country and autonomous system of a bootnode address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeoInfo {
+    pub country: String,
+    pub asn: String,
+}
+
+/// MaxMind lookups for bootnode addresses. Either database may be missing,
+/// in which case its label is reported as "unknown".
+pub struct GeoIp {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Opens the databases given on the command line, or returns `None` if
+    /// GeoIP isn't configured.
+    pub fn open(cli: &Cli) -> Result<Option<Self>> {
+        if cli.geoip_db.is_none() && cli.geoip_asn_db.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            country: cli.geoip_db.as_deref().map(open_reader).transpose()?,
+            asn: cli.geoip_asn_db.as_deref().map(open_reader).transpose()?,
+        }))
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let country = self
+            .country
+            .as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Country>(ip).ok())
+            .and_then(|record| record.country?.iso_code)
+            .unwrap_or(UNKNOWN)
+            .to_string();
+        let asn = self
+            .asn
+            .as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Asn>(ip).ok())
+            .and_then(|record| record.autonomous_system_number)
+            .map_or_else(|| UNKNOWN.to_string(), |asn| format!("AS{}", asn));
+        GeoInfo { country, asn }
+    }
+}
+
+fn open_reader(path: &Path) -> Result<Reader<Vec<u8>>> {
+    Reader::open_readfile(path).with_context(|| format!("Failed to open GeoIP database {:?}", path))
+}
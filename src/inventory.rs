@@ -0,0 +1,274 @@
+// src/inventory.rs
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    bootnode::node_binary,
+    cli::{BootnodesConfig, Cli},
+};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// File written and removed again to check that a directory is writable.
+const WRITE_PROBE_FILE: &str = ".bootyspector-write-probe";
+
+enum Location {
+    /// Reloaded when its mtime changes.
+    File {
+        modified: Option<SystemTime>,
+    },
+    Remote {
+        url: Url,
+        client: reqwest::Client,
+    },
+}
+
+/// The bootnodes config in use, along with where to reload it from.
+/// A failed reload keeps the last good config so a bad edit or a flaky
+/// endpoint never stops testing.
+pub struct BootnodesSource {
+    location: Location,
+    config: BootnodesConfig,
+}
+
+impl BootnodesSource {
+    /// Loads the initial config. Unlike reloads, any error here is fatal.
+    pub async fn open(cli: &Cli) -> Result<Self> {
+        let location = match remote_url(&cli.bootnodes_config)? {
+            Some(url) => Location::Remote {
+                url,
+                client: fetch_client(cli)?,
+            },
+            None => Location::File {
+                modified: modified(&cli.bootnodes_config),
+            },
+        };
+
+        let mut source = Self {
+            location,
+            config: BootnodesConfig::default(),
+        };
+        source.config = source.fetch(cli).await?;
+        check_chain_specs(&source.config, cli, !cli.allow_missing_chain_specs)?;
+        Ok(source)
+    }
+
+    pub fn config(&self) -> &BootnodesConfig {
+        &self.config
+    }
+
+    /// Reloads the config, keeping the previous one if that fails.
+    pub async fn refresh(&mut self, cli: &Cli) {
+        if let Location::File { modified: last } = &mut self.location {
+            let current = modified(&cli.bootnodes_config);
+            if current == *last {
+                return;
+            }
+            // remember it even if the reload fails, so a broken edit is reported once
+            *last = current;
+            info!("Bootnodes config changed, reloading");
+        }
+
+        match self.fetch(cli).await {
+            Ok(config) => {
+                // missing specs only fail their own tests after startup
+                let _ = check_chain_specs(&config, cli, false);
+                self.config = config;
+            }
+            Err(e) => warn!(
+                "Failed to reload bootnodes config, keeping the previous one: {:#}",
+                e
+            ),
+        }
+    }
+
+    async fn fetch(&self, cli: &Cli) -> Result<BootnodesConfig> {
+        let config = match &self.location {
+            Location::File { .. } => BootnodesConfig::load(&cli.bootnodes_config)?,
+            Location::Remote { url, client } => {
+                let response = client
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .with_context(|| format!("Failed to fetch bootnodes config from {}", url))?;
+                let content_type = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                let content = response.text().await?;
+                parse_remote(&content, content_type.as_deref())
+                    .with_context(|| format!("Failed to parse bootnodes config from {}", url))?
+            }
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Parses a fetched config by its `Content-Type`. URLs rarely end in the
+/// file's extension, and raw file hosts serve everything as text, so any
+/// other type is tried as JSON and then as YAML.
+fn parse_remote(content: &str, content_type: Option<&str>) -> Result<BootnodesConfig> {
+    let media_type = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    if media_type == "application/json" || media_type.ends_with("+json") {
+        return Ok(serde_json::from_str(content)?);
+    }
+    if media_type.contains("yaml") {
+        return Ok(serde_yaml::from_str(content)?);
+    }
+    match serde_json::from_str(content) {
+        Ok(config) => Ok(config),
+        Err(json_error) => serde_yaml::from_str(content).map_err(|yaml_error| {
+            anyhow::anyhow!("neither JSON ({}) nor YAML ({})", json_error, yaml_error)
+        }),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn fetch_client(cli: &Cli) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().timeout(FETCH_TIMEOUT);
+    if let Some(proxy) = &cli.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+    }
+    Ok(builder.build()?)
+}
+
+fn remote_url(location: &Path) -> Result<Option<Url>> {
+    let Some(location) = location.to_str() else {
+        return Ok(None);
+    };
+    if !location.starts_with("http://") && !location.starts_with("https://") {
+        return Ok(None);
+    }
+    let url = Url::parse(location)
+        .with_context(|| format!("Invalid bootnodes config URL {}", location))?;
+    info!("Fetching bootnodes config from {} every cycle", url);
+    Ok(Some(url))
+}
+
+/// Node binaries needed by the networks in the config.
+pub fn required_binaries<'a>(config: &BootnodesConfig, cli: &'a Cli) -> BTreeSet<&'a Path> {
+    config
+        .networks
+        .values()
+        .map(|network| node_binary(cli, &network.command_id))
+        .collect()
+}
+
+/// Fails with a list of the binaries the config needs that don't exist or
+/// aren't executable, rather than failing every test that would use them.
+pub fn check_binaries(config: &BootnodesConfig, cli: &Cli) -> Result<()> {
+    let unusable: Vec<String> = required_binaries(config, cli)
+        .into_iter()
+        .filter_map(|binary| {
+            let problem = match resolve_binary(binary) {
+                None => "not found",
+                Some(path) if !is_executable(&path) => "not executable",
+                Some(_) => return None,
+            };
+            Some(format!("  {} ({})", binary.display(), problem))
+        })
+        .collect();
+    if !unusable.is_empty() {
+        anyhow::bail!("Unusable node binaries:\n{}", unusable.join("\n"));
+    }
+    Ok(())
+}
+
+/// Creates `dir` if needed and checks that files can be written in it, so a
+/// permissions problem fails at startup instead of in every test. `what`
+/// names the directory in errors, e.g. "output dir".
+pub fn check_writable_dir(dir: &Path, what: &str) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {} {:?}", what, dir))?;
+    let probe = dir.join(WRITE_PROBE_FILE);
+    fs::write(&probe, b"").with_context(|| format!("Failed to write to {} {:?}", what, dir))?;
+    fs::remove_file(&probe).with_context(|| format!("Failed to remove write probe {:?}", probe))?;
+    Ok(())
+}
+
+/// Where `binary` would be run from: the path itself, or a `$PATH` lookup for
+/// a bare name, the way `Command` resolves it.
+fn resolve_binary(binary: &Path) -> Option<PathBuf> {
+    if binary.components().count() > 1 {
+        return binary.exists().then(|| binary.to_path_buf());
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Reports chain specs referenced by the config that don't exist, as an error
+/// if `fatal` and as a warning otherwise.
+fn check_chain_specs(config: &BootnodesConfig, cli: &Cli, fatal: bool) -> Result<()> {
+    let missing_specs = config.missing_chain_specs(&cli.chain_spec_dir);
+    if missing_specs.is_empty() {
+        return Ok(());
+    }
+
+    let list = missing_specs
+        .iter()
+        .map(|path| format!("  {}", path.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if fatal {
+        anyhow::bail!("Missing chain specs:\n{}", list);
+    }
+    warn!(
+        "Missing chain specs, tests for these networks will fail:\n{}",
+        list
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = r#"{"polkadot": {"commandId": "polkadot", "members": {"operator": ["/dns/a.example/tcp/30333/p2p/12D3KooWA"]}}}"#;
+    const YAML: &str = "polkadot:\n  commandId: polkadot\n  members:\n    operator:\n      - /dns/a.example/tcp/30333/p2p/12D3KooWA\n";
+
+    #[test]
+    fn remote_config_format_follows_content_type() {
+        for (content, content_type) in [
+            (JSON, Some("application/json; charset=utf-8")),
+            (YAML, Some("application/yaml")),
+            (YAML, Some("text/x-yaml")),
+        ] {
+            let config = parse_remote(content, content_type).unwrap();
+            assert!(config.networks.contains_key("polkadot"));
+        }
+        // a declared type is trusted
+        assert!(parse_remote(YAML, Some("application/json")).is_err());
+    }
+
+    #[test]
+    fn untyped_remote_config_is_tried_as_json_then_yaml() {
+        for content in [JSON, YAML] {
+            for content_type in [None, Some("text/plain; charset=utf-8")] {
+                let config = parse_remote(content, content_type).unwrap();
+                assert!(config.networks.contains_key("polkadot"));
+            }
+        }
+        assert!(parse_remote("not: [a config", None).is_err());
+    }
+}
@@ -0,0 +1,20 @@
+//! Tests Polkadot bootnodes by starting a node against each one and checking
+//! that it discovers peers.
+//!
+//! The `bootyspector` binary is a thin loop around [`run_test_cycle`]. To test
+//! a single bootnode without the rest of the machinery, call [`test_bootnode`]
+//! directly and use the returned [`TestResult`].
+pub mod bootnode;
+pub mod cli;
+pub mod geo;
+pub mod inventory;
+pub mod metrics;
+pub mod notify;
+pub mod report;
+pub mod runner;
+pub mod storage;
+
+pub use bootnode::{init_port_pool, test_bootnode};
+pub use cli::{BootnodesConfig, Cli, NetworkConfig};
+pub use metrics::{MetricsState, TestResult, TestStatus};
+pub use runner::{run_test_cycle, CycleContext, TestCycleSummary, TestKey};
@@ -1,137 +1,81 @@
 // main.rs
-mod bootnode;
-mod cli;
-mod metrics;
-
-use anyhow::{Context, Result};
-use futures::future::join_all;
-use std::{
-    fs::{self, File},
-    io::Write,
-    path::Path,
-    sync::{atomic::Ordering, Arc},
-    time::Duration,
+use anyhow::Result;
+use rand::Rng;
+use std::{process::ExitCode, sync::Arc, time::Duration};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::sleep,
 };
-use tokio::{sync::Semaphore, time::sleep};
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-use crate::{
-    bootnode::{test_bootnode, NEXT_PORT},
-    cli::Cli,
-    metrics::{MetricsHandle, TestResult},
+use bootyspector::{
+    bootnode::{
+        binary_version, discovered_metric_seen, init_port_pool, parse_node_version,
+        remove_stale_data_dirs, test_bootnode, watch_bootnode,
+    },
+    cli::{Cli, LogFormat, NetworkConfig},
+    inventory::{check_binaries, check_writable_dir, required_binaries, BootnodesSource},
+    metrics::{MetricsHandle, TestStatus},
+    report::FinalReport,
+    runner::{run_test_cycle, CycleContext, OnDemandTester},
 };
 
-async fn run_test_cycle(
-    cli: &Cli,
-    bootnodes: &cli::BootnodesConfig,
-    metrics_state: Arc<metrics::MetricsState>,
-    semaphore: Arc<Semaphore>,
-) -> Result<TestCycleSummary> {
-    let mut tasks = Vec::new();
-    let mut total_tests = 0;
-
-    for (network, network_config) in &bootnodes.networks {
-        let command_id = network_config.command_id.clone();
-        for (operator, bootnodes) in &network_config.members {
-            for bootnode in bootnodes {
-                total_tests += 1;
-                let cli = cli.clone();
-                let network = network.clone();
-                let operator = operator.clone();
-                let bootnode = bootnode.clone();
-                let command_id = command_id.clone();
-                let semaphore = Arc::clone(&semaphore);
-                let metrics = Arc::clone(&metrics_state);
-
-                tasks.push(tokio::spawn(async move {
-                    let _permit = semaphore.acquire().await?;
-                    let result =
-                        test_bootnode(&cli, &operator, &network, &bootnode, &command_id).await?;
-
-                    metrics.record_test_result(&network, &operator, &bootnode, &result);
-                    Ok::<_, anyhow::Error>(result)
-                }));
-            }
-        }
-    }
+async fn wait_for_shutdown_signal() -> Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
 
-    let mut success_count = 0;
-    let mut failed_tests = Vec::new();
-
-    for result in join_all(tasks).await {
-        match result? {
-            Ok(test_result) => {
-                if test_result.valid {
-                    success_count += 1;
-                } else {
-                    failed_tests.push((
-                        test_result.network.clone(),
-                        test_result.id.clone(),
-                        test_result.bootnode.clone(),
-                    ));
-                }
-                update_results(
-                    &cli.output_dir.join("results.json"),
-                    &test_result.id,
-                    &test_result.network,
-                    &test_result,
-                )
-                .await?;
-            }
-            Err(e) => {
-                error!("Test failed: {}", e);
-            }
-        }
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        _ = sigint.recv() => info!("Received SIGINT"),
     }
 
-    Ok(TestCycleSummary {
-        total_tests,
-        success_count,
-        failed_tests,
-    })
+    Ok(())
 }
 
-#[derive(Debug)]
-struct TestCycleSummary {
-    total_tests: usize,
-    success_count: usize,
-    failed_tests: Vec<(String, String, String)>, // (network, operator, bootnode)
+/// Runs the shutdown signal handler, cancelling the returned token on a signal.
+fn shutdown_on_signal() -> CancellationToken {
+    let shutdown = CancellationToken::new();
+    let token = shutdown.clone();
+    tokio::spawn(async move {
+        match wait_for_shutdown_signal().await {
+            Ok(()) => {
+                info!("Shutting down, cleaning up running nodes...");
+                token.cancel();
+            }
+            Err(e) => error!("Failed to install signal handlers: {}", e),
+        }
+    });
+    shutdown
 }
 
-async fn update_results(
-    output_file: &Path,
-    operator: &str,
-    network: &str,
-    result: &TestResult,
-) -> Result<()> {
-    let content = if output_file.exists() {
-        fs::read_to_string(output_file)?
-    } else {
-        "{}".to_string()
-    };
-
-    let mut json: serde_json::Value = serde_json::from_str(&content)?;
-
-    if let serde_json::Value::Object(ref mut map) = json {
-        let operator_obj = map
-            .entry(operator)
-            .or_insert(serde_json::json!({}))
-            .as_object_mut()
-            .context("Invalid JSON structure")?;
+/// `--bootnode`: tests one address without a bootnodes config and prints the
+/// result as JSON, exiting nonzero if it failed.
+async fn test_single_bootnode(cli: &Cli, bootnode: &str, network: &str) -> Result<ExitCode> {
+    init_port_pool(cli.base_port, &[cli.prometheus_port]);
+    check_writable_dir(&cli.data_dir, "data dir")?;
 
-        operator_obj.insert(network.to_string(), serde_json::to_value(result)?);
+    let network_config = NetworkConfig {
+        command_id: cli.command_id.clone(),
+        ..Default::default()
+    };
+    let shutdown = shutdown_on_signal();
+    if cli.watch {
+        watch_bootnode(cli, network, bootnode, &network_config, &shutdown).await?;
+        return Ok(ExitCode::SUCCESS);
     }
+    let result = test_bootnode(cli, "cli", network, bootnode, &network_config, &shutdown).await?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
 
-    let tmp_file = output_file.with_extension("tmp");
-    let mut file = File::create(&tmp_file)?;
-    file.write_all(serde_json::to_string_pretty(&json)?.as_bytes())?;
-    fs::rename(tmp_file, output_file)?;
-
-    Ok(())
+    Ok(if result.valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let cli = Cli::load()?;
 
     let log_level = if cli.debug {
@@ -139,60 +83,225 @@ async fn main() -> Result<()> {
     } else {
         tracing::Level::INFO
     };
-    tracing_subscriber::fmt().with_max_level(log_level).init();
+    let subscriber = tracing_subscriber::fmt().with_max_level(log_level);
+    match cli.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+
+    if let (Some(bootnode), Some(network)) = (&cli.bootnode, &cli.network) {
+        return test_single_bootnode(&cli, bootnode, network).await;
+    }
 
-    let metrics_handle = MetricsHandle::new()?;
+    let metrics_handle = MetricsHandle::new(Duration::from_secs(cli.uptime_window_hours * 3600))?;
     let metrics_state = metrics_handle.state.clone();
 
-    // metrics server
-    tokio::spawn(metrics_handle.serve(cli.prometheus_port));
+    let pushgateway = cli
+        .pushgateway
+        .as_ref()
+        .map(|url| metrics_handle.pushgateway(url, &cli.pushgateway_job))
+        .transpose()?;
+
+    init_port_pool(cli.base_port, &[cli.prometheus_port]);
+    check_writable_dir(&cli.output_dir, "output dir")?;
+    check_writable_dir(&cli.data_dir, "data dir")?;
+    // a dry run touches nothing on disk
+    if !cli.dry_run {
+        match remove_stale_data_dirs(&cli.data_dir, cli.keep_failed_data) {
+            Ok(0) => {}
+            Ok(removed) => info!(
+                "Removed {} stale node data dirs from a previous run",
+                removed
+            ),
+            Err(e) => warn!("Failed to clean up stale node data dirs: {}", e),
+        }
+    }
+
+    let mut bootnodes = BootnodesSource::open(&cli).await?;
+    check_binaries(bootnodes.config(), &cli)?;
+
+    // versions are cached, so tests report them without running the binary again
+    let mut outdated = Vec::new();
+    for binary in required_binaries(bootnodes.config(), &cli) {
+        let version = binary_version(binary).await;
+        match &version {
+            Some(version) => {
+                info!("Using {} {}", binary.display(), version);
+                metrics_state.record_binary_version(&binary.display().to_string(), version);
+            }
+            None => warn!("Failed to get the version of {}", binary.display()),
+        }
+
+        // encointer isn't released in step with polkadot, so its version can't be compared
+        let Some(min_version) = &cli.min_node_version else {
+            continue;
+        };
+        if binary == cli.encointer_binary {
+            continue;
+        }
+        let problem = match version.as_deref().and_then(parse_node_version) {
+            None => Some("version unknown".to_string()),
+            Some(parsed) => {
+                let is_outdated = parse_node_version(min_version).is_some_and(|min| parsed < min);
+                metrics_state.record_binary_outdated(
+                    &binary.display().to_string(),
+                    version.as_deref().unwrap_or_default(),
+                    min_version,
+                    is_outdated,
+                );
+                is_outdated.then(|| {
+                    format!(
+                        "{} is older than {}",
+                        version.unwrap_or_default(),
+                        min_version
+                    )
+                })
+            }
+        };
+        if let Some(problem) = problem {
+            warn!("Node binary {}: {}", binary.display(), problem);
+            outdated.push(format!("  {} ({})", binary.display(), problem));
+        }
+    }
+    if cli.strict_version && !outdated.is_empty() {
+        anyhow::bail!("Outdated node binaries:\n{}", outdated.join("\n"));
+    }
 
-    NEXT_PORT.store(cli.base_port, Ordering::SeqCst);
-    fs::create_dir_all(&cli.output_dir)?;
+    // cancels in-flight tests so their nodes are killed and data dirs removed
+    let shutdown = shutdown_on_signal();
 
-    let bootnodes: cli::BootnodesConfig = serde_json::from_reader(
-        File::open(&cli.bootnodes_config).context("Failed to open bootnodes config")?,
-    )?;
+    let ctx = CycleContext::new(&cli, Arc::clone(&metrics_state), shutdown.clone())?;
 
-    let semaphore = Arc::new(Semaphore::new(cli.max_concurrent));
+    // metrics server
+    let tester = Arc::new(OnDemandTester::new(&ctx, bootnodes.config()));
+    tokio::spawn(metrics_handle.serve(
+        cli.prometheus_port,
+        cli.output_dir.join("results.json"),
+        cli.metrics_token.clone(),
+        tester.clone(),
+    ));
+
+    let mut previously_failed = Vec::new();
+    let mut cycles = 0;
+    let mut any_cycle_failed = false;
+    let mut report = FinalReport::default();
 
     // continuous cycles
     info!("Starting continuous bootnode testing...");
-    loop {
+    let exit_code = loop {
         let cycle_start = std::time::Instant::now();
 
-        match run_test_cycle(&cli, &bootnodes, metrics_state.clone(), semaphore.clone()).await {
+        bootnodes.refresh(&cli).await;
+        tester.update(bootnodes.config());
+
+        let cycle_failed = match run_test_cycle(&ctx, bootnodes.config(), &previously_failed).await
+        {
             Ok(summary) => {
+                let cycle_failed = !summary.failed_tests.is_empty();
+                metrics_state.record_cycle(
+                    summary.total_tests,
+                    summary.success_count,
+                    summary.failed_tests.len(),
+                    cycle_start.elapsed(),
+                );
                 info!(
-                    "Test cycle completed: {}/{} successful, {} failed. Cycle duration: {:?}",
+                    "Test cycle completed: {}/{} successful, {} failed, {} deferred. Cycle duration: {:?}",
                     summary.success_count,
                     summary.total_tests,
                     summary.failed_tests.len(),
+                    summary.deferred_count,
                     cycle_start.elapsed(),
                 );
 
+                let no_metric = summary
+                    .results
+                    .iter()
+                    .any(|result| matches!(result.status, TestStatus::NoMetricFound));
+                if cycles == 0 && no_metric && !discovered_metric_seen() {
+                    warn!(
+                        "No node reported the {} metric in the first cycle; \
+                         if the node binaries renamed it, set metric_names.discovered in the config",
+                        cli.metric_names.discovered
+                    );
+                }
+
                 if !summary.failed_tests.is_empty() {
                     info!("Failed bootnodes:");
-                    for (network, operator, bootnode) in summary.failed_tests {
+                    for (network, operator, bootnode) in &summary.failed_tests {
                         info!("- {}/{}: {}", operator, network, bootnode);
                     }
                 }
+                report.add_cycle(&summary);
+                previously_failed = summary.failed_tests;
+                cycle_failed
             }
             Err(e) => {
                 error!("Test cycle failed: {}", e);
+                true
+            }
+        };
+
+        metrics_state.mark_ready();
+
+        if let Some(pushgateway) = &pushgateway {
+            if let Err(e) = pushgateway.push().await {
+                warn!("Failed to push metrics to the Pushgateway: {}", e);
             }
         }
 
+        cycles += 1;
+        any_cycle_failed |= cycle_failed;
+        // a bounded run cut short by shutdown didn't test everything it was asked to
+        let bounded = cli.once || cli.max_cycles.is_some();
+
+        if shutdown.is_cancelled() {
+            info!("Shutdown complete");
+            break if bounded {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+
+        if cli.once || cli.dry_run || cli.max_cycles.is_some_and(|max| cycles >= max) {
+            break if any_cycle_failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+
         // Wait before starting the next cycle
         // Calculate delay to maintain consistent cycle time
         let cycle_duration = cycle_start.elapsed();
         let target_cycle_time = Duration::from_secs(cli.interval);
-        if cycle_duration < target_cycle_time {
-            let delay = target_cycle_time - cycle_duration;
+        if cycle_duration >= target_cycle_time {
+            info!("Cycle took longer than target time");
+        }
+        // spread instances started at the same time, so they don't all hit
+        // shared relay RPC endpoints at once
+        let jitter = Duration::from_millis(
+            rand::thread_rng().gen_range(0..=cli.cycle_jitter_secs.saturating_mul(1000)),
+        );
+        let delay = target_cycle_time.saturating_sub(cycle_duration) + jitter;
+        if !delay.is_zero() {
             info!("Waiting {:?} before next cycle", delay);
-            sleep(delay).await;
-        } else {
-            info!("Cycle took longer than target time, starting next cycle immediately");
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = shutdown.cancelled() => {
+                    info!("Shutdown complete");
+                    break if bounded {
+                        ExitCode::FAILURE
+                    } else {
+                        ExitCode::SUCCESS
+                    };
+                }
+            }
         }
+    };
+
+    if !cli.dry_run {
+        report.print(cli.report_format)?;
     }
+    Ok(exit_code)
 }
@@ -24,16 +24,45 @@ groups:
      description: "Check duration > 30s for {{ $labels.provider }}/{{ $labels.network }}"
 */
 use anyhow::Result;
-use prometheus::{Encoder, IntGaugeVec, Registry, TextEncoder};
-use serde::Serialize;
-use std::sync::Arc;
+use chrono::{SecondsFormat, Utc};
+use futures::future::BoxFuture;
+use prometheus::{
+    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::Infallible,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
 use tracing::error;
-use warp::Filter;
+use url::Url;
+use warp::{http::StatusCode, Filter};
+
+use crate::{
+    bootnode::{ip_family, parse_multiaddr},
+    cli::SyncMode,
+    geo::GeoInfo,
+};
 
 #[derive(Debug)]
 pub struct MetricsResult {
     pub peers: u64,
     pub status: MetricsStatus,
+    /// best block as a percentage of the sync target, if the node reports both
+    pub sync_progress: Option<f64>,
+    pub inbound_peers: Option<u64>,
+    pub outbound_peers: Option<u64>,
+    /// values of the `--peer-metrics` the node reports, by metric name
+    pub peer_counts: BTreeMap<String, u64>,
 }
 
 #[derive(Debug)]
@@ -46,14 +75,72 @@ pub enum MetricsStatus {
 #[serde(rename_all = "camelCase")]
 pub enum TestStatus {
     Success,
+    /// the node's metrics server never came up
+    MetricsServerDown,
     MetricsUnavailable,
     NoMetricFound,
     Timeout,
     NodeStartupFailed,
+    InvalidMultiaddr,
+    Unreachable,
+    DnsResolutionFailed,
+    PeerIdMismatch,
+    /// the relay chain RPC a parachain node needs was unreachable, so the
+    /// bootnode couldn't be tested
+    RelayRpcUnavailable,
+    /// the test task panicked, a bug in the tester rather than the bootnode
+    Panicked,
+    /// not tested because the host was short on memory
+    Deferred,
+}
+
+impl TestStatus {
+    pub const ALL: [TestStatus; 13] = [
+        TestStatus::Success,
+        TestStatus::MetricsServerDown,
+        TestStatus::MetricsUnavailable,
+        TestStatus::NoMetricFound,
+        TestStatus::Timeout,
+        TestStatus::NodeStartupFailed,
+        TestStatus::InvalidMultiaddr,
+        TestStatus::Unreachable,
+        TestStatus::DnsResolutionFailed,
+        TestStatus::PeerIdMismatch,
+        TestStatus::RelayRpcUnavailable,
+        TestStatus::Panicked,
+        TestStatus::Deferred,
+    ];
+
+    /// Stable metric label for the status, independent of any error text.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TestStatus::Success => "none",
+            TestStatus::NodeStartupFailed => "startup_failed",
+            TestStatus::MetricsServerDown => "metrics_server_down",
+            TestStatus::MetricsUnavailable => "metrics_unavailable",
+            TestStatus::NoMetricFound => "no_metrics",
+            TestStatus::Timeout => "timeout",
+            TestStatus::InvalidMultiaddr => "invalid_multiaddr",
+            TestStatus::Unreachable => "unreachable",
+            TestStatus::DnsResolutionFailed => "dns_failed",
+            TestStatus::PeerIdMismatch => "peer_id_mismatch",
+            TestStatus::RelayRpcUnavailable => "relay_rpc_unavailable",
+            TestStatus::Panicked => "panicked",
+            TestStatus::Deferred => "deferred",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DnsResolution {
+    pub hostname: String,
+    pub duration_ms: u64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct TestResult {
+    /// when the test started, RFC 3339 in UTC
+    pub timestamp: String,
     pub id: String,
     pub network: String,
     pub bootnode: String,
@@ -62,16 +149,196 @@ pub struct TestResult {
     pub discovered_peers: u64,
     pub status: TestStatus,
     pub error_details: Option<String>,
+    pub dns_resolution: Option<DnsResolution>,
+    /// address the pre-flight check reached, or the first resolved one
+    pub ip: Option<IpAddr>,
+    /// TCP reachability per address family ("v4"/"v6") the bootnode resolved to
+    pub ip_families: Option<BTreeMap<String, bool>>,
+    /// TCP connect, plus TLS and websocket upgrade where the transport has them
+    pub handshake_latency_ms: Option<u64>,
+    /// relay chain RPC endpoint a parachain node was started with
+    pub relay_rpc: Option<String>,
+    /// address the relay RPC check connected to
+    pub relay_rpc_ip: Option<IpAddr>,
+    /// `--sync` mode the test node ran with, `None` for the node's default
+    pub sync_mode: Option<SyncMode>,
+    /// version of the node binary the test ran
+    pub tester_binary_version: Option<String>,
+    pub sync_progress: Option<f64>,
+    pub inbound_peers: Option<u64>,
+    pub outbound_peers: Option<u64>,
+    /// last values of the `--peer-metrics` the node reported, by metric name
+    pub peer_counts: Option<BTreeMap<String, u64>>,
+    /// share of passing checks within `--uptime-window-hours`, including this one
+    pub uptime_ratio: Option<f64>,
+    /// checks failed in a row up to and including this one, 0 if it passed
+    pub consecutive_failures: Option<u64>,
+    /// time from node start until it saw its first peer
+    pub time_to_first_peer_ms: Option<u64>,
+    /// resident memory of the test node when the check ended
+    pub node_rss_bytes: Option<u64>,
+    /// CPU time the test node used over its lifetime
+    pub node_cpu_time_ms: Option<u64>,
+    /// tail of the node's stderr for failed tests when `--capture-logs` is set
+    pub node_log: Option<String>,
+}
+
+impl TestResult {
+    /// A failed result with nothing but the failure filled in, for tests that
+    /// ended before a node ran.
+    pub fn failed(
+        operator: &str,
+        network: &str,
+        bootnode: &str,
+        status: TestStatus,
+        error_details: String,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            id: operator.to_string(),
+            network: network.to_string(),
+            bootnode: bootnode.to_string(),
+            valid: false,
+            test_duration_ms: 0,
+            discovered_peers: 0,
+            status,
+            error_details: Some(error_details),
+            dns_resolution: None,
+            ip: None,
+            ip_families: None,
+            handshake_latency_ms: None,
+            relay_rpc: None,
+            relay_rpc_ip: None,
+            sync_mode: None,
+            tester_binary_version: None,
+            sync_progress: None,
+            inbound_peers: None,
+            outbound_peers: None,
+            peer_counts: None,
+            uptime_ratio: None,
+            consecutive_failures: None,
+            time_to_first_peer_ms: None,
+            node_rss_bytes: None,
+            node_cpu_time_ms: None,
+            node_log: None,
+        }
+    }
 }
 
-#[derive(Clone)]
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest `POST /test` body accepted.
+const TEST_REQUEST_LIMIT: u64 = 16 * 1024;
+
+/// Body of a `POST /test` request.
+#[derive(Debug, Deserialize)]
+pub struct TestRequest {
+    pub network: String,
+    pub operator: String,
+    pub bootnode: String,
+    pub command_id: String,
+}
+
+/// Why a `POST /test` is refused before anything runs.
+#[derive(Debug, thiserror::Error)]
+pub enum TestRequestError {
+    #[error("invalid {0} {1:?}")]
+    InvalidName(&'static str, String),
+    #[error("unknown network {0:?}")]
+    UnknownNetwork(String),
+}
+
+/// What serves `POST /test`, implemented by the runner.
+pub trait OnDemand: Send + Sync {
+    fn run(&self, request: TestRequest) -> BoxFuture<'_, Result<TestResult>>;
+}
+
+/// Number of recent results per bootnode considered for flapping.
+const FLAP_WINDOW: usize = 10;
+/// Pass/fail changes within the window at which a bootnode counts as flapping.
+const FLAP_MIN_TRANSITIONS: usize = 3;
+/// Buckets of `bootnode_test_duration_ms`, from a fast DNS failure up to the
+/// longest test timeouts.
+const TEST_DURATION_BUCKETS_MS: &[f64] = &[
+    500.0, 1000.0, 2500.0, 5000.0, 10000.0, 15000.0, 20000.0, 30000.0, 45000.0, 60000.0, 90000.0,
+    120000.0, 180000.0, 300000.0,
+];
+
 pub struct MetricsState {
+    /// set once the first test cycle has completed, drives `/ready`
+    ready: AtomicBool,
     bootnode_status: IntGaugeVec,
     last_check_duration: IntGaugeVec,
+    test_duration: HistogramVec,
+    tests_total: IntCounterVec,
+    test_failures_total: IntCounterVec,
+    dns_resolution_time: IntGaugeVec,
+    chain_sync_progress: GaugeVec,
+    peer_connections: IntGaugeVec,
+    peer_counts: IntGaugeVec,
+    failure_reasons: IntCounterVec,
+    time_to_first_peer: IntGaugeVec,
+    cycle_total_tests: IntGauge,
+    cycle_success_count: IntGauge,
+    cycle_failed_count: IntGauge,
+    cycle_duration: IntGauge,
+    concurrency_in_use: IntGauge,
+    concurrency_limit: IntGauge,
+    concurrency_high_water: IntGauge,
+    flapping: IntGaugeVec,
+    /// last `FLAP_WINDOW` pass/fail outcomes per (network, provider, bootnode)
+    recent_outcomes: Mutex<HashMap<(String, String, String), VecDeque<bool>>>,
+    uptime_ratio: GaugeVec,
+    uptime_window: Duration,
+    uptime: Mutex<HashMap<(String, String, String), UptimeWindow>>,
+    consecutive_failures: IntGaugeVec,
+    /// failures since the last pass per (network, provider, bootnode)
+    failure_streaks: Mutex<HashMap<(String, String, String), u64>>,
+    seconds_since_last_success: IntGaugeVec,
+    /// when each (network, provider, bootnode) last passed
+    last_success: Mutex<HashMap<(String, String, String), Instant>>,
+    handshake_latency: IntGaugeVec,
+    protocol_checks: IntCounterVec,
+    sync_mode_checks: IntCounterVec,
+    node_memory: IntGaugeVec,
+    binary_info: IntGaugeVec,
+    binary_outdated: IntGaugeVec,
+    node_cpu: GaugeVec,
+    family_reachable: IntGaugeVec,
+    family_checks: IntCounterVec,
+    connection_type_success: IntGaugeVec,
+    bootnode_geo: IntGaugeVec,
+    /// labels of the current `bootnode_geo` series, to drop it when they change
+    geo_labels: Mutex<HashMap<(String, String, String), GeoInfo>>,
+}
+
+/// Results within the uptime window, with a running pass count so the ratio
+/// is updated without rescanning the window.
+#[derive(Debug, Default)]
+struct UptimeWindow {
+    results: VecDeque<(Instant, bool)>,
+    passed: usize,
+}
+
+impl UptimeWindow {
+    fn record(&mut self, valid: bool, window: Duration) -> f64 {
+        let now = Instant::now();
+        self.results.push_back((now, valid));
+        self.passed += valid as usize;
+
+        while let Some(&(at, passed)) = self.results.front() {
+            if now.duration_since(at) <= window {
+                break;
+            }
+            self.results.pop_front();
+            self.passed -= passed as usize;
+        }
+
+        self.passed as f64 / self.results.len() as f64
+    }
 }
 
 impl MetricsState {
-    pub fn new() -> Result<(Self, Registry)> {
+    pub fn new(uptime_window: Duration) -> Result<(Self, Registry)> {
         let registry = Registry::new();
 
         let bootnode_status = IntGaugeVec::new(
@@ -90,37 +357,376 @@ impl MetricsState {
             &["network", "provider", "bootnode"],
         )?;
 
+        let test_duration = HistogramVec::new(
+            prometheus::histogram_opts!(
+                "bootnode_test_duration_ms",
+                "Distribution of test durations in milliseconds",
+                TEST_DURATION_BUCKETS_MS.to_vec()
+            ),
+            &["network", "command_id"],
+        )?;
+
+        let tests_total = IntCounterVec::new(
+            prometheus::opts!("bootspector_tests_total", "Tests run since start"),
+            &["network", "command_id"],
+        )?;
+
+        let test_failures_total = IntCounterVec::new(
+            prometheus::opts!(
+                "bootspector_test_failures_total",
+                "Failed tests since start"
+            ),
+            &["network", "command_id"],
+        )?;
+
+        let dns_resolution_time = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_dns_resolution_ms",
+                "Duration of the last DNS resolution of the bootnode hostname in milliseconds"
+            ),
+            &["network", "provider", "hostname"],
+        )?;
+
+        let chain_sync_progress = GaugeVec::new(
+            prometheus::opts!(
+                "bootnode_chain_sync_progress_percent",
+                "Best block as a percentage of the sync target at the end of the last check"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let peer_connections = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_peer_connections",
+                "Open peer connections of the test node by direction at the end of the last check"
+            ),
+            &["network", "provider", "bootnode", "direction"],
+        )?;
+
+        let peer_counts = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_peer_count",
+                "Peer count metrics of the test node at the end of the last check"
+            ),
+            &["network", "provider", "bootnode", "metric"],
+        )?;
+
+        let failure_reasons = IntCounterVec::new(
+            prometheus::opts!("bootnode_failures_total", "Failed bootnode tests by reason"),
+            &["network", "provider", "reason"],
+        )?;
+
+        let time_to_first_peer = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_time_to_first_peer_ms",
+                "Time from node start until it discovered its first peer in the last check"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let cycle_total_tests = IntGauge::new(
+            "bootspector_cycle_total_tests",
+            "Bootnodes tested in the last completed cycle",
+        )?;
+        let cycle_success_count = IntGauge::new(
+            "bootspector_cycle_success_count",
+            "Bootnodes that passed in the last completed cycle",
+        )?;
+        let cycle_failed_count = IntGauge::new(
+            "bootspector_cycle_failed_count",
+            "Bootnodes that failed in the last completed cycle",
+        )?;
+        let cycle_duration = IntGauge::new(
+            "bootspector_cycle_duration_ms",
+            "Duration of the last completed cycle in milliseconds",
+        )?;
+
+        let concurrency_in_use = IntGauge::new(
+            "bootnode_concurrency_in_use",
+            "Tests currently holding a concurrency permit",
+        )?;
+        let concurrency_limit = IntGauge::new(
+            "bootnode_concurrency_limit",
+            "Maximum number of concurrent tests (--max-concurrent)",
+        )?;
+        let concurrency_high_water = IntGauge::new(
+            "bootnode_concurrency_in_use_max",
+            "Highest number of concurrent tests seen since startup",
+        )?;
+
+        let flapping = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_flapping",
+                "Whether the bootnode alternated between passing and failing in its recent checks"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let uptime_ratio = GaugeVec::new(
+            prometheus::opts!(
+                "bootnode_uptime_ratio",
+                "Share of passing checks within the uptime window (0-1)"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let seconds_since_last_success = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_seconds_since_last_success",
+                "Seconds since the bootnode last passed a check, updated on every scrape"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let consecutive_failures = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_consecutive_failures",
+                "Checks the bootnode failed in a row since it last passed"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let handshake_latency = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_handshake_latency_ms",
+                "Connection handshake time to the bootnode in the last check, -1 if it failed"
+            ),
+            &["network", "provider", "bootnode"],
+        )?;
+
+        let protocol_checks = IntCounterVec::new(
+            prometheus::opts!(
+                "bootnode_protocol_checks_total",
+                "Bootnode checks by transport protocol and result"
+            ),
+            &["network", "provider", "protocol", "result"],
+        )?;
+
+        let sync_mode_checks = IntCounterVec::new(
+            prometheus::opts!(
+                "bootnode_sync_mode_checks_total",
+                "Bootnode checks by the sync mode of the test node and result"
+            ),
+            &["network", "provider", "sync_mode", "result"],
+        )?;
+
+        let node_memory = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_test_node_memory_bytes",
+                "Resident memory of the last test node of the network when its check ended"
+            ),
+            &["network", "provider"],
+        )?;
+
+        let node_cpu = GaugeVec::new(
+            prometheus::opts!(
+                "bootnode_test_node_cpu_seconds",
+                "CPU time used by the last test node of the network over its lifetime"
+            ),
+            &["network", "provider"],
+        )?;
+
+        let binary_info = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_tester_binary_info",
+                "Version of each node binary used to run tests, always 1"
+            ),
+            &["binary", "version"],
+        )?;
+
+        let binary_outdated = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_tester_binary_outdated",
+                "Whether the node binary is older than --min-node-version (1) or not (0)"
+            ),
+            &["binary", "version", "min_version"],
+        )?;
+
+        let build_info = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootspector_build_info",
+                "Version, git commit and compiler of this build, always 1"
+            ),
+            &["version", "git_sha", "rustc_version"],
+        )?;
+        build_info
+            .with_label_values(&[
+                env!("CARGO_PKG_VERSION"),
+                env!("BUILD_GIT_SHA"),
+                env!("BUILD_RUSTC_VERSION"),
+            ])
+            .set(1);
+
+        let family_reachable = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_ip_family_reachable",
+                "Whether the bootnode accepted TCP connections over this address family in the last check"
+            ),
+            &["network", "provider", "bootnode", "ip_family"],
+        )?;
+
+        let family_checks = IntCounterVec::new(
+            prometheus::opts!(
+                "bootnode_ip_family_checks_total",
+                "Bootnode checks by the address family that was used and result"
+            ),
+            &["network", "provider", "ip_family", "result"],
+        )?;
+
+        let connection_type_success = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_connection_type_success",
+                "Whether the node with this peer id passed over this transport in the last cycle"
+            ),
+            &["network", "provider", "peer_id", "protocol"],
+        )?;
+
+        let bootnode_geo = IntGaugeVec::new(
+            prometheus::opts!(
+                "bootnode_geo",
+                "Country and autonomous system of the bootnode address, always 1"
+            ),
+            &["network", "provider", "bootnode", "country", "asn"],
+        )?;
+
         registry.register(Box::new(bootnode_status.clone()))?;
         registry.register(Box::new(last_check_duration.clone()))?;
+        registry.register(Box::new(test_duration.clone()))?;
+        registry.register(Box::new(tests_total.clone()))?;
+        registry.register(Box::new(test_failures_total.clone()))?;
+        registry.register(Box::new(dns_resolution_time.clone()))?;
+        registry.register(Box::new(chain_sync_progress.clone()))?;
+        registry.register(Box::new(peer_connections.clone()))?;
+        registry.register(Box::new(peer_counts.clone()))?;
+        registry.register(Box::new(failure_reasons.clone()))?;
+        registry.register(Box::new(time_to_first_peer.clone()))?;
+        registry.register(Box::new(cycle_total_tests.clone()))?;
+        registry.register(Box::new(cycle_success_count.clone()))?;
+        registry.register(Box::new(cycle_failed_count.clone()))?;
+        registry.register(Box::new(cycle_duration.clone()))?;
+        registry.register(Box::new(concurrency_in_use.clone()))?;
+        registry.register(Box::new(concurrency_limit.clone()))?;
+        registry.register(Box::new(concurrency_high_water.clone()))?;
+        registry.register(Box::new(flapping.clone()))?;
+        registry.register(Box::new(uptime_ratio.clone()))?;
+        registry.register(Box::new(consecutive_failures.clone()))?;
+        registry.register(Box::new(seconds_since_last_success.clone()))?;
+        registry.register(Box::new(handshake_latency.clone()))?;
+        registry.register(Box::new(protocol_checks.clone()))?;
+        registry.register(Box::new(sync_mode_checks.clone()))?;
+        registry.register(Box::new(node_memory.clone()))?;
+        registry.register(Box::new(node_cpu.clone()))?;
+        registry.register(Box::new(binary_info.clone()))?;
+        registry.register(Box::new(binary_outdated.clone()))?;
+        registry.register(Box::new(build_info))?;
+        registry.register(Box::new(family_reachable.clone()))?;
+        registry.register(Box::new(family_checks.clone()))?;
+        registry.register(Box::new(connection_type_success.clone()))?;
+        registry.register(Box::new(bootnode_geo.clone()))?;
 
         Ok((
             Self {
+                ready: AtomicBool::new(false),
                 bootnode_status,
                 last_check_duration,
+                test_duration,
+                tests_total,
+                test_failures_total,
+                dns_resolution_time,
+                chain_sync_progress,
+                peer_connections,
+                peer_counts,
+                failure_reasons,
+                time_to_first_peer,
+                cycle_total_tests,
+                cycle_success_count,
+                cycle_failed_count,
+                cycle_duration,
+                concurrency_in_use,
+                concurrency_limit,
+                concurrency_high_water,
+                flapping,
+                recent_outcomes: Mutex::new(HashMap::new()),
+                uptime_ratio,
+                uptime_window,
+                uptime: Mutex::new(HashMap::new()),
+                consecutive_failures,
+                failure_streaks: Mutex::new(HashMap::new()),
+                seconds_since_last_success,
+                last_success: Mutex::new(HashMap::new()),
+                handshake_latency,
+                protocol_checks,
+                sync_mode_checks,
+                node_memory,
+                binary_info,
+                binary_outdated,
+                node_cpu,
+                family_reachable,
+                family_checks,
+                connection_type_success,
+                bootnode_geo,
+                geo_labels: Mutex::new(HashMap::new()),
             },
             registry,
         ))
     }
 
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn record_concurrency(&self, in_use: usize, limit: usize) {
+        let in_use = in_use as i64;
+        self.concurrency_in_use.set(in_use);
+        self.concurrency_limit.set(limit as i64);
+        if in_use > self.concurrency_high_water.get() {
+            self.concurrency_high_water.set(in_use);
+        }
+    }
+
+    pub fn record_cycle(&self, total: usize, success: usize, failed: usize, duration: Duration) {
+        self.cycle_total_tests.set(total as i64);
+        self.cycle_success_count.set(success as i64);
+        self.cycle_failed_count.set(failed as i64);
+        self.cycle_duration.set(duration.as_millis() as i64);
+    }
+
+    /// Updates all per-bootnode metrics and returns the bootnode's uptime ratio.
     pub fn record_test_result(
         &self,
         network: &str,
         provider: &str,
         bootnode: &str,
+        command_id: &str,
         result: &TestResult,
-    ) {
-        let reason = if result.valid {
-            "none"
-        } else {
-            match result.status {
-                TestStatus::NodeStartupFailed => "startup_failed",
-                TestStatus::MetricsUnavailable => "metrics_unavailable",
-                TestStatus::NoMetricFound => "no_metrics",
-                TestStatus::Timeout => "timeout",
-                TestStatus::Success => unreachable!(),
-            }
-        };
+    ) -> f64 {
+        let reason = result.status.reason();
+
+        self.tests_total
+            .with_label_values(&[network, command_id])
+            .inc();
+        if !result.valid {
+            self.test_failures_total
+                .with_label_values(&[network, command_id])
+                .inc();
+            self.failure_reasons
+                .with_label_values(&[network, provider, reason])
+                .inc();
+        }
 
+        // one series per bootnode: drop the previous reason, or a recovered
+        // bootnode would keep reporting 0 under its old failure_reason
+        for other in TestStatus::ALL.iter().map(TestStatus::reason) {
+            if other != reason {
+                let _ = self
+                    .bootnode_status
+                    .remove_label_values(&[network, provider, bootnode, other]);
+            }
+        }
         self.bootnode_status
             .with_label_values(&[network, provider, bootnode, reason])
             .set(if result.valid { 1 } else { 0 });
@@ -128,7 +734,326 @@ impl MetricsState {
         self.last_check_duration
             .with_label_values(&[network, provider, bootnode])
             .set(result.test_duration_ms as i64);
+        self.test_duration
+            .with_label_values(&[network, command_id])
+            .observe(result.test_duration_ms as f64);
+
+        self.record_outcome(network, provider, bootnode, result.valid);
+
+        let outcome = if result.valid { "success" } else { "failure" };
+        let protocol = parse_multiaddr(bootnode).map_or("unknown", |addr| addr.transport.label());
+        self.protocol_checks
+            .with_label_values(&[network, provider, protocol, outcome])
+            .inc();
+        let sync_mode = result.sync_mode.map_or("default", |mode| mode.as_str());
+        self.sync_mode_checks
+            .with_label_values(&[network, provider, sync_mode, outcome])
+            .inc();
+        let uptime = self.record_uptime(network, provider, bootnode, result.valid);
+        if result.valid {
+            self.last_success
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(
+                    (
+                        network.to_string(),
+                        provider.to_string(),
+                        bootnode.to_string(),
+                    ),
+                    Instant::now(),
+                );
+            self.seconds_since_last_success
+                .with_label_values(&[network, provider, bootnode])
+                .set(0);
+        }
+
+        if let Some(rss_bytes) = result.node_rss_bytes {
+            self.node_memory
+                .with_label_values(&[network, provider])
+                .set(rss_bytes as i64);
+        }
+        if let Some(cpu_time_ms) = result.node_cpu_time_ms {
+            self.node_cpu
+                .with_label_values(&[network, provider])
+                .set(cpu_time_ms as f64 / 1000.0);
+        }
+
+        if let Some(ip) = &result.ip {
+            self.family_checks
+                .with_label_values(&[network, provider, ip_family(ip), outcome])
+                .inc();
+        }
+        for (family, reachable) in result.ip_families.iter().flatten() {
+            self.family_reachable
+                .with_label_values(&[network, provider, bootnode, family])
+                .set(*reachable as i64);
+        }
+
+        // only tcp transports are probed; a missing value after an unreachable
+        // result means the handshake itself failed
+        let latency = match (result.handshake_latency_ms, &result.status) {
+            (Some(ms), _) => Some(ms as i64),
+            (None, TestStatus::Unreachable) => Some(-1),
+            (None, _) => None,
+        };
+        if let Some(latency) = latency {
+            self.handshake_latency
+                .with_label_values(&[network, provider, bootnode])
+                .set(latency);
+        }
+
+        match result.time_to_first_peer_ms {
+            Some(ms) => self
+                .time_to_first_peer
+                .with_label_values(&[network, provider, bootnode])
+                .set(ms as i64),
+            None => {
+                let _ = self
+                    .time_to_first_peer
+                    .remove_label_values(&[network, provider, bootnode]);
+            }
+        }
+
+        if let Some(dns) = &result.dns_resolution {
+            self.record_dns_resolution(network, provider, &dns.hostname, dns.duration_ms);
+        }
+
+        if let Some(progress) = result.sync_progress {
+            self.record_chain_sync(network, provider, bootnode, progress);
+        }
+
+        if let (Some(inbound), Some(outbound)) = (result.inbound_peers, result.outbound_peers) {
+            self.record_peer_connections(network, provider, bootnode, inbound, outbound);
+        }
+
+        for (metric, count) in result.peer_counts.iter().flatten() {
+            self.peer_counts
+                .with_label_values(&[network, provider, bootnode, metric])
+                .set(*count as i64);
+        }
+
+        uptime
+    }
+
+    pub fn record_connection_type(
+        &self,
+        network: &str,
+        provider: &str,
+        peer_id: &str,
+        protocol: &str,
+        success: bool,
+    ) {
+        self.connection_type_success
+            .with_label_values(&[network, provider, peer_id, protocol])
+            .set(success as i64);
     }
+
+    pub fn record_binary_version(&self, binary: &str, version: &str) {
+        self.binary_info
+            .with_label_values(&[binary, version])
+            .set(1);
+    }
+
+    pub fn record_binary_outdated(
+        &self,
+        binary: &str,
+        version: &str,
+        min_version: &str,
+        outdated: bool,
+    ) {
+        self.binary_outdated
+            .with_label_values(&[binary, version, min_version])
+            .set(outdated as i64);
+    }
+
+    pub fn record_geo(&self, network: &str, provider: &str, bootnode: &str, geo: GeoInfo) {
+        let mut geo_labels = self
+            .geo_labels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let key = (
+            network.to_string(),
+            provider.to_string(),
+            bootnode.to_string(),
+        );
+        if let Some(old) = geo_labels.get(&key) {
+            if *old == geo {
+                return;
+            }
+            let _ = self.bootnode_geo.remove_label_values(&[
+                network,
+                provider,
+                bootnode,
+                &old.country,
+                &old.asn,
+            ]);
+        }
+        self.bootnode_geo
+            .with_label_values(&[network, provider, bootnode, &geo.country, &geo.asn])
+            .set(1);
+        geo_labels.insert(key, geo);
+    }
+
+    fn record_uptime(&self, network: &str, provider: &str, bootnode: &str, valid: bool) -> f64 {
+        let ratio = self
+            .uptime
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((
+                network.to_string(),
+                provider.to_string(),
+                bootnode.to_string(),
+            ))
+            .or_default()
+            .record(valid, self.uptime_window);
+        self.uptime_ratio
+            .with_label_values(&[network, provider, bootnode])
+            .set(ratio);
+        ratio
+    }
+
+    /// Sets `bootnode_seconds_since_last_success` to the time elapsed now, so
+    /// a bootnode that stops being tested, e.g. because the tester is stuck,
+    /// keeps growing staler between checks.
+    pub fn refresh_seconds_since_last_success(&self) {
+        let last_success = self
+            .last_success
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for ((network, provider, bootnode), at) in last_success.iter() {
+            self.seconds_since_last_success
+                .with_label_values(&[network, provider, bootnode])
+                .set(at.elapsed().as_secs() as i64);
+        }
+    }
+
+    /// Updates the bootnode's failure streak and returns its new length.
+    pub fn record_consecutive_failures(
+        &self,
+        network: &str,
+        provider: &str,
+        bootnode: &str,
+        valid: bool,
+    ) -> u64 {
+        let mut streaks = self
+            .failure_streaks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let streak = streaks
+            .entry((
+                network.to_string(),
+                provider.to_string(),
+                bootnode.to_string(),
+            ))
+            .or_default();
+        *streak = if valid { 0 } else { *streak + 1 };
+        self.consecutive_failures
+            .with_label_values(&[network, provider, bootnode])
+            .set(*streak as i64);
+        *streak
+    }
+
+    fn record_outcome(&self, network: &str, provider: &str, bootnode: &str, valid: bool) {
+        let mut recent_outcomes = self
+            .recent_outcomes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let window = recent_outcomes
+            .entry((
+                network.to_string(),
+                provider.to_string(),
+                bootnode.to_string(),
+            ))
+            .or_default();
+        if window.len() == FLAP_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(valid);
+
+        let transitions = window
+            .iter()
+            .zip(window.iter().skip(1))
+            .filter(|(a, b)| a != b)
+            .count();
+        self.flapping
+            .with_label_values(&[network, provider, bootnode])
+            .set((transitions >= FLAP_MIN_TRANSITIONS) as i64);
+    }
+
+    pub fn record_peer_connections(
+        &self,
+        network: &str,
+        provider: &str,
+        bootnode: &str,
+        inbound: u64,
+        outbound: u64,
+    ) {
+        self.peer_connections
+            .with_label_values(&[network, provider, bootnode, "in"])
+            .set(inbound as i64);
+        self.peer_connections
+            .with_label_values(&[network, provider, bootnode, "out"])
+            .set(outbound as i64);
+    }
+
+    pub fn record_chain_sync(&self, network: &str, provider: &str, bootnode: &str, progress: f64) {
+        self.chain_sync_progress
+            .with_label_values(&[network, provider, bootnode])
+            .set(progress);
+    }
+
+    pub fn record_dns_resolution(
+        &self,
+        network: &str,
+        provider: &str,
+        hostname: &str,
+        duration_ms: u64,
+    ) {
+        self.dns_resolution_time
+            .with_label_values(&[network, provider, hostname])
+            .set(duration_ms as i64);
+    }
+}
+
+/// Pushes the registry to a Prometheus Pushgateway, for runs that exit before
+/// anything scrapes them.
+pub struct Pushgateway {
+    client: reqwest::Client,
+    url: Url,
+    state: Arc<MetricsState>,
+    registry: Registry,
+}
+
+impl Pushgateway {
+    /// Replaces all metrics of the job's group with the current registry.
+    pub async fn push(&self) -> Result<()> {
+        self.state.refresh_seconds_since_last_success();
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        self.client
+            .put(self.url.clone())
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                TextEncoder::new().format_type(),
+            )
+            .body(buffer)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Whether `authorization` carries the expected bearer token, or no token is
+/// required. Compared in constant time so the token can't be guessed by timing.
+fn bearer_matches(expected: Option<&str>, authorization: Option<&str>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let provided = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    provided.as_bytes().ct_eq(expected.as_bytes()).into()
 }
 
 pub struct MetricsHandle {
@@ -137,31 +1062,193 @@ pub struct MetricsHandle {
 }
 
 impl MetricsHandle {
-    pub fn new() -> Result<Self> {
-        let (state, registry) = MetricsState::new()?;
+    pub fn new(uptime_window: Duration) -> Result<Self> {
+        let (state, registry) = MetricsState::new(uptime_window)?;
         Ok(Self {
             state: Arc::new(state),
             registry,
         })
     }
 
-    pub async fn serve(self, port: u16) -> Result<()> {
-        let metrics_route = warp::path!("metrics").map(move || {
-            let encoder = TextEncoder::new();
-            let metric_families = self.registry.gather();
-            let mut buffer = Vec::new();
-            encoder
-                .encode(&metric_families, &mut buffer)
-                .unwrap_or_else(|e| {
-                    error!("Failed to encode metrics: {}", e);
+    pub fn pushgateway(&self, base: &Url, job: &str) -> Result<Pushgateway> {
+        let mut url = base.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid Pushgateway URL"))?
+            .pop_if_empty()
+            .extend(["metrics", "job", job]);
+        Ok(Pushgateway {
+            client: reqwest::Client::builder().timeout(PUSH_TIMEOUT).build()?,
+            url,
+            state: Arc::clone(&self.state),
+            registry: self.registry.clone(),
+        })
+    }
+
+    pub async fn serve(
+        self,
+        port: u16,
+        results_file: PathBuf,
+        metrics_token: Option<String>,
+        tester: Arc<dyn OnDemand>,
+    ) -> Result<()> {
+        let state = Arc::clone(&self.state);
+        let scrape_state = Arc::clone(&self.state);
+        let test_token = metrics_token.clone();
+        let metrics_route = warp::path!("metrics")
+            .and(warp::header::optional::<String>("authorization"))
+            .map(move |authorization: Option<String>| {
+                if !bearer_matches(metrics_token.as_deref(), authorization.as_deref()) {
+                    return warp::reply::with_status(
+                        String::from("unauthorized"),
+                        StatusCode::UNAUTHORIZED,
+                    );
+                }
+                scrape_state.refresh_seconds_since_last_success();
+                let encoder = TextEncoder::new();
+                let metric_families = self.registry.gather();
+                let mut buffer = Vec::new();
+                encoder
+                    .encode(&metric_families, &mut buffer)
+                    .unwrap_or_else(|e| {
+                        error!("Failed to encode metrics: {}", e);
+                    });
+                let body = String::from_utf8(buffer).unwrap_or_else(|e| {
+                    error!("Failed to convert metrics to string: {}", e);
+                    String::from("# Error encoding metrics")
                 });
-            String::from_utf8(buffer).unwrap_or_else(|e| {
-                error!("Failed to convert metrics to string: {}", e);
-                String::from("# Error encoding metrics")
-            })
+                warp::reply::with_status(body, StatusCode::OK)
+            });
+
+        // read on every request so it always reflects the last write of results.json
+        let results_route = warp::path!("results").map(move || {
+            let results = fs::read_to_string(&results_file)
+                .ok()
+                .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+                .unwrap_or_else(|| serde_json::json!({}));
+            warp::reply::json(&results)
         });
 
-        warp::serve(metrics_route).run(([127, 0, 0, 1], port)).await;
+        let health_route = warp::path!("health").map(|| "ok");
+
+        let ready_route = warp::path!("ready").map(move || {
+            if state.is_ready() {
+                warp::reply::with_status("ready", StatusCode::OK)
+            } else {
+                warp::reply::with_status("not ready", StatusCode::SERVICE_UNAVAILABLE)
+            }
+        });
+
+        // tests one bootnode right away; never open, as it starts nodes
+        let test_route = warp::path!("test")
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::content_length_limit(TEST_REQUEST_LIMIT))
+            .and(warp::body::json())
+            .and_then(move |authorization: Option<String>, request: TestRequest| {
+                let authorized = test_token.is_some()
+                    && bearer_matches(test_token.as_deref(), authorization.as_deref());
+                let tester = Arc::clone(&tester);
+                async move {
+                    if !authorized {
+                        return Ok::<_, Infallible>(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "error": "unauthorized" })),
+                            StatusCode::UNAUTHORIZED,
+                        ));
+                    }
+                    Ok(match tester.run(request).await {
+                        Ok(result) => {
+                            warp::reply::with_status(warp::reply::json(&result), StatusCode::OK)
+                        }
+                        Err(e) => {
+                            let status = if e.is::<TestRequestError>() {
+                                StatusCode::BAD_REQUEST
+                            } else {
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            };
+                            warp::reply::with_status(
+                                warp::reply::json(&serde_json::json!({ "error": e.to_string() })),
+                                status,
+                            )
+                        }
+                    })
+                }
+            });
+
+        let routes = metrics_route
+            .or(results_route)
+            .or(health_route)
+            .or(ready_route)
+            .or(test_route);
+
+        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REASONS: [&str; 13] = [
+        "none",
+        "metrics_server_down",
+        "metrics_unavailable",
+        "no_metrics",
+        "timeout",
+        "startup_failed",
+        "invalid_multiaddr",
+        "unreachable",
+        "dns_failed",
+        "peer_id_mismatch",
+        "relay_rpc_unavailable",
+        "panicked",
+        "deferred",
+    ];
+
+    #[test]
+    fn every_status_maps_to_a_fixed_reason() {
+        let reasons: Vec<_> = TestStatus::ALL.iter().map(TestStatus::reason).collect();
+        assert_eq!(reasons, REASONS);
+    }
+
+    #[test]
+    fn error_text_never_becomes_a_label() {
+        let (state, registry) = MetricsState::new(Duration::from_secs(3600)).unwrap();
+        let error = "connection refused by 203.0.113.7:30333 after 3 attempts";
+        for status in TestStatus::ALL {
+            let result = TestResult::failed(
+                "operator",
+                "polkadot",
+                "/dns/a.example",
+                status,
+                error.to_string(),
+            );
+            state.record_test_result(
+                "polkadot",
+                "operator",
+                "/dns/a.example",
+                "polkadot",
+                &result,
+            );
+        }
+
+        let mut reasons = 0;
+        for family in registry.gather() {
+            for metric in family.get_metric() {
+                for label in metric.get_label() {
+                    assert!(!label.get_value().contains(error));
+                    if matches!(label.get_name(), "reason" | "failure_reason") {
+                        reasons += 1;
+                        assert!(
+                            REASONS.contains(&label.get_value()),
+                            "unexpected {} {:?}",
+                            label.get_name(),
+                            label.get_value()
+                        );
+                    }
+                }
+            }
+        }
+        assert!(reasons > 0);
+    }
+}
@@ -0,0 +1,602 @@
+// src/notify.rs
+use anyhow::Result;
+use futures::future::{join_all, BoxFuture, FutureExt};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    cli::Cli,
+    metrics::{TestResult, TestStatus},
+};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+/// Longest error snippet quoted in a Slack message.
+const SLACK_ERROR_SNIPPET_CHARS: usize = 200;
+/// Slack rejects messages over these limits as a whole.
+const SLACK_SECTION_CHARS: usize = 3000;
+const SLACK_MAX_BLOCKS: usize = 50;
+
+/// Change in a bootnode's pass/fail state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Unchanged,
+    Failed,
+    Recovered,
+}
+
+/// Whether each (network, operator, bootnode) is currently alerted on as failing.
+#[derive(Debug, Default)]
+pub struct AlertState {
+    failing: Mutex<HashMap<(String, String, String), bool>>,
+}
+
+impl AlertState {
+    /// Records the result and returns how the bootnode's state changed. A
+    /// bootnode counts as failing once it failed `alert_after` checks in a row,
+    /// and as recovered on its next pass. Bootnodes we haven't seen yet count as
+    /// passing, so a node that is already down at startup alerts once.
+    pub fn record(&self, result: &TestResult, alert_after: u64) -> Transition {
+        let mut failing = self.failing.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (
+            result.network.clone(),
+            result.id.clone(),
+            result.bootnode.clone(),
+        );
+        let was_failing = failing.get(&key).copied().unwrap_or(false);
+        let is_failing = if result.valid {
+            false
+        } else {
+            // a failure that doesn't reach the threshold leaves the state as it was
+            was_failing || result.consecutive_failures.unwrap_or(1) >= alert_after
+        };
+        failing.insert(key, is_failing);
+        match (was_failing, is_failing) {
+            (false, true) => Transition::Failed,
+            (true, false) => Transition::Recovered,
+            _ => Transition::Unchanged,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Failed,
+    Recovered,
+}
+
+/// A bootnode that started failing or recovered.
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub kind: AlertKind,
+    pub network: String,
+    pub operator: String,
+    pub bootnode: String,
+    pub status: TestStatus,
+    pub error_details: Option<String>,
+    /// whether the network is marked critical in the bootnodes config
+    pub critical: bool,
+}
+
+impl AlertEvent {
+    fn error_details(&self) -> &str {
+        self.error_details.as_deref().unwrap_or("no details")
+    }
+}
+
+/// Somewhere alerts are delivered to.
+pub trait AlertSink: Send + Sync {
+    /// Used in delivery error logs.
+    fn name(&self) -> &'static str;
+
+    fn notify<'a>(&'a self, event: &'a AlertEvent) -> BoxFuture<'a, Result<()>>;
+
+    /// Delivers all events of a cycle. Sinks that would rather send one
+    /// message per cycle than one per event override this.
+    fn notify_cycle<'a>(&'a self, events: &'a [AlertEvent]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            for result in join_all(events.iter().map(|event| self.notify(event))).await {
+                result?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Sends the request, failing on error statuses as well.
+async fn send(request: reqwest::RequestBuilder) -> Result<()> {
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// POSTs a JSON body per failure to a generic webhook.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: Url,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    network: &'a str,
+    operator: &'a str,
+    bootnode: &'a str,
+    status: &'a TestStatus,
+    error_details: Option<&'a str>,
+}
+
+impl AlertSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn notify<'a>(&'a self, event: &'a AlertEvent) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if event.kind != AlertKind::Failed {
+                return Ok(());
+            }
+            let payload = WebhookPayload {
+                network: &event.network,
+                operator: &event.operator,
+                bootnode: &event.bootnode,
+                status: &event.status,
+                error_details: event.error_details.as_deref(),
+            };
+            send(self.client.post(self.url.clone()).json(&payload)).await
+        }
+        .boxed()
+    }
+}
+
+/// Room to post failures to on a Matrix homeserver.
+pub struct MatrixSink {
+    client: reqwest::Client,
+    homeserver: Url,
+    token: String,
+    room: String,
+    txn_counter: AtomicU64,
+}
+
+impl MatrixSink {
+    /// `PUT /_matrix/client/v3/rooms/{room}/send/m.room.message/{txn}` for the next message.
+    fn send_url(&self) -> Result<Url> {
+        let txn_id = format!(
+            "bootyspector-{}-{}",
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            self.txn_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid Matrix homeserver URL"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room,
+                "send",
+                "m.room.message",
+                &txn_id,
+            ]);
+        Ok(url)
+    }
+
+    fn message(event: &AlertEvent) -> serde_json::Value {
+        serde_json::json!({
+            "msgtype": "m.text",
+            "body": format!(
+                "Bootnode failing: {}/{} ({:?})\n{}\n{}",
+                event.operator, event.network, event.status, event.bootnode, event.error_details()
+            ),
+            "format": "org.matrix.custom.html",
+            "formatted_body": format!(
+                "<b>Bootnode failing:</b> {}/{} (<code>{:?}</code>)<br/><code>{}</code><br/>{}",
+                event.operator, event.network, event.status, event.bootnode, event.error_details()
+            ),
+        })
+    }
+}
+
+impl AlertSink for MatrixSink {
+    fn name(&self) -> &'static str {
+        "Matrix"
+    }
+
+    fn notify<'a>(&'a self, event: &'a AlertEvent) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if event.kind != AlertKind::Failed {
+                return Ok(());
+            }
+            let request = self
+                .client
+                .put(self.send_url()?)
+                .bearer_auth(&self.token)
+                .json(&Self::message(event));
+            send(request).await
+        }
+        .boxed()
+    }
+}
+
+/// PagerDuty Events API v2 integration, used only for critical networks. The
+/// dedup key ties the trigger and resolve events of a bootnode to one incident.
+pub struct PagerDutySink {
+    client: reqwest::Client,
+    routing_key: String,
+}
+
+impl PagerDutySink {
+    fn event(&self, event: &AlertEvent) -> serde_json::Value {
+        let dedup_key = format!(
+            "bootyspector/{}/{}/{}",
+            event.network, event.operator, event.bootnode
+        );
+        match event.kind {
+            AlertKind::Recovered => serde_json::json!({
+                "routing_key": self.routing_key,
+                "event_action": "resolve",
+                "dedup_key": dedup_key,
+            }),
+            AlertKind::Failed => serde_json::json!({
+                "routing_key": self.routing_key,
+                "event_action": "trigger",
+                "dedup_key": dedup_key,
+                "payload": {
+                    "summary": format!(
+                        "Bootnode {}/{} failing: {}",
+                        event.operator,
+                        event.network,
+                        event.status.reason()
+                    ),
+                    "source": event.bootnode,
+                    "severity": "critical",
+                    "component": event.network,
+                    "group": event.operator,
+                    "custom_details": {
+                        "status": event.status,
+                        "error_details": event.error_details,
+                    },
+                },
+            }),
+        }
+    }
+}
+
+impl AlertSink for PagerDutySink {
+    fn name(&self) -> &'static str {
+        "PagerDuty"
+    }
+
+    fn notify<'a>(&'a self, event: &'a AlertEvent) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if !event.critical {
+                return Ok(());
+            }
+            send(
+                self.client
+                    .post(PAGERDUTY_EVENTS_URL)
+                    .json(&self.event(event)),
+            )
+            .await
+        }
+        .boxed()
+    }
+}
+
+/// Slack incoming webhook. A cycle's failures and recoveries are posted as a
+/// single message, grouped by operator.
+pub struct SlackSink {
+    client: reqwest::Client,
+    webhook: Url,
+}
+
+impl SlackSink {
+    /// Block Kit message for the events. Kept within Slack's limits: an
+    /// operator's lines are split over sections, and what doesn't fit in the
+    /// block limit is summed up in a last "…and N more" block.
+    fn message(events: &[AlertEvent]) -> serde_json::Value {
+        // with the number of events each block lists
+        let mut blocks: Vec<(serde_json::Value, usize)> = Vec::new();
+        let mut summary = Vec::new();
+        for (kind, title) in [
+            (AlertKind::Failed, "Bootnodes failing"),
+            (AlertKind::Recovered, "Bootnodes recovered"),
+        ] {
+            let mut by_operator: BTreeMap<&str, Vec<&AlertEvent>> = BTreeMap::new();
+            for event in events.iter().filter(|event| event.kind == kind) {
+                by_operator.entry(&event.operator).or_default().push(event);
+            }
+            if by_operator.is_empty() {
+                continue;
+            }
+
+            let count: usize = by_operator.values().map(Vec::len).sum();
+            summary.push(format!("{}: {}", title, count));
+            blocks.push((
+                serde_json::json!({
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": format!("{} ({})", title, count) },
+                }),
+                0,
+            ));
+            for (operator, events) in by_operator {
+                let lines: Vec<String> = events
+                    .iter()
+                    .map(|event| match event.kind {
+                        AlertKind::Failed => format!(
+                            "• `{}` `{}` *{}*: {}",
+                            escape(&event.network),
+                            escape(&event.bootnode),
+                            event.status.reason(),
+                            escape(&snippet(event.error_details()))
+                        ),
+                        AlertKind::Recovered => format!(
+                            "• `{}` `{}`",
+                            escape(&event.network),
+                            escape(&event.bootnode)
+                        ),
+                    })
+                    .collect();
+                let heading = format!("*{}*", escape(operator));
+                for (text, count) in sections(&heading, &lines) {
+                    blocks.push((
+                        serde_json::json!({
+                            "type": "section",
+                            "text": { "type": "mrkdwn", "text": text },
+                        }),
+                        count,
+                    ));
+                }
+            }
+        }
+
+        if blocks.len() > SLACK_MAX_BLOCKS {
+            let omitted: usize = blocks
+                .drain(SLACK_MAX_BLOCKS - 1..)
+                .map(|(_, count)| count)
+                .sum();
+            blocks.push((
+                serde_json::json!({
+                    "type": "context",
+                    "elements": [{ "type": "mrkdwn", "text": format!("…and {} more", omitted) }],
+                }),
+                0,
+            ));
+        }
+
+        serde_json::json!({
+            // shown in notifications, where blocks aren't rendered
+            "text": summary.join(", "),
+            "blocks": blocks.into_iter().map(|(block, _)| block).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl AlertSink for SlackSink {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    fn notify<'a>(&'a self, event: &'a AlertEvent) -> BoxFuture<'a, Result<()>> {
+        self.notify_cycle(std::slice::from_ref(event))
+    }
+
+    fn notify_cycle<'a>(&'a self, events: &'a [AlertEvent]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let message = Self::message(events);
+            send(self.client.post(self.webhook.clone()).json(&message)).await
+        }
+        .boxed()
+    }
+}
+
+/// `heading` and `lines` split into section texts of at most
+/// `SLACK_SECTION_CHARS`, each with the number of lines it holds. Every
+/// section repeats the heading.
+fn sections(heading: &str, lines: &[String]) -> Vec<(String, usize)> {
+    let mut sections = Vec::new();
+    let mut text = heading.to_string();
+    let mut count = 0;
+    for line in lines {
+        let line = truncate(line, SLACK_SECTION_CHARS - heading.chars().count() - 1);
+        if count > 0 && text.chars().count() + 1 + line.chars().count() > SLACK_SECTION_CHARS {
+            sections.push((std::mem::replace(&mut text, heading.to_string()), count));
+            count = 0;
+        }
+        text.push('\n');
+        text.push_str(&line);
+        count += 1;
+    }
+    sections.push((text, count));
+    sections
+}
+
+/// `text` cut to `max` chars, ending in "…" if anything was cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+/// Escapes the characters Slack's mrkdwn treats as control sequences.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// First line of an error, cut to `SLACK_ERROR_SNIPPET_CHARS`.
+fn snippet(details: &str) -> String {
+    let line = details.lines().next().unwrap_or_default();
+    if line.chars().count() <= SLACK_ERROR_SNIPPET_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(SLACK_ERROR_SNIPPET_CHARS).collect();
+    format!("{}…", cut)
+}
+
+/// Turns test results into alert events and fans them out to the sinks
+/// configured on the command line.
+pub struct Notifier {
+    sinks: Vec<Box<dyn AlertSink>>,
+    state: AlertState,
+    /// `--alert-after-failures`
+    alert_after: u64,
+    pending: Mutex<Vec<AlertEvent>>,
+}
+
+impl Notifier {
+    pub fn new(cli: &Cli) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+
+        let mut sinks: Vec<Box<dyn AlertSink>> = Vec::new();
+        if let Some(url) = &cli.alert_webhook {
+            sinks.push(Box::new(WebhookSink {
+                client: client.clone(),
+                url: url.clone(),
+            }));
+        }
+        if let Some(webhook) = &cli.slack_webhook {
+            sinks.push(Box::new(SlackSink {
+                client: client.clone(),
+                webhook: webhook.clone(),
+            }));
+        }
+        if let Some(routing_key) = &cli.pagerduty_routing_key {
+            sinks.push(Box::new(PagerDutySink {
+                client: client.clone(),
+                routing_key: routing_key.clone(),
+            }));
+        }
+        if let (Some(homeserver), Some(token), Some(room)) =
+            (&cli.matrix_homeserver, &cli.matrix_token, &cli.matrix_room)
+        {
+            sinks.push(Box::new(MatrixSink {
+                client,
+                homeserver: homeserver.clone(),
+                token: token.clone(),
+                room: room.clone(),
+                txn_counter: AtomicU64::new(0),
+            }));
+        }
+
+        Ok(Self {
+            sinks,
+            state: AlertState::default(),
+            alert_after: cli.alert_after_failures,
+            pending: Mutex::default(),
+        })
+    }
+
+    /// Tracks the result and queues an event if the bootnode just started
+    /// failing or recovered. `critical` is whether its network pages.
+    pub fn handle_result(&self, result: &TestResult, critical: bool) {
+        let kind = match self.state.record(result, self.alert_after) {
+            Transition::Unchanged => return,
+            Transition::Failed => AlertKind::Failed,
+            Transition::Recovered => AlertKind::Recovered,
+        };
+        info!(
+            "Bootnode {}/{} {}: {}",
+            result.id,
+            result.network,
+            if kind == AlertKind::Failed {
+                "started failing"
+            } else {
+                "recovered"
+            },
+            result.bootnode
+        );
+
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(AlertEvent {
+                kind,
+                network: result.network.clone(),
+                operator: result.id.clone(),
+                bootnode: result.bootnode.clone(),
+                status: result.status.clone(),
+                error_details: result.error_details.clone(),
+                critical,
+            });
+    }
+
+    /// Delivers the cycle's events to all sinks concurrently. Each sink gets
+    /// `WEBHOOK_TIMEOUT`, so a slow one delays the cycle by at most that, while
+    /// a final cycle's alerts still go out before the process exits.
+    pub async fn flush_cycle(&self) {
+        let events =
+            std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        if events.is_empty() || self.sinks.is_empty() {
+            return;
+        }
+        let deliveries = self.sinks.iter().map(|sink| async {
+            match tokio::time::timeout(WEBHOOK_TIMEOUT, sink.notify_cycle(&events)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Failed to deliver {} alert: {}", sink.name(), e),
+                Err(_) => warn!("Timed out delivering {} alert", sink.name()),
+            }
+        });
+        join_all(deliveries).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(operator: &str, index: usize) -> AlertEvent {
+        AlertEvent {
+            kind: AlertKind::Failed,
+            network: "polkadot".to_string(),
+            operator: operator.to_string(),
+            bootnode: format!(
+                "/dns/bootnode-{}.{}.example.com/tcp/30333/wss/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp",
+                index, operator
+            ),
+            status: TestStatus::Timeout,
+            error_details: Some(format!("peer <{}> & others didn't answer {}", index, "x".repeat(300))),
+            critical: false,
+        }
+    }
+
+    #[test]
+    fn slack_message_stays_within_limits() {
+        let mut events: Vec<_> = (0..100).map(|i| failed("big-operator", i)).collect();
+        events.extend((0..60).map(|i| failed(&format!("operator-{:02}", i), i)));
+        let message = SlackSink::message(&events);
+        let blocks = message["blocks"].as_array().unwrap();
+
+        assert_eq!(blocks.len(), SLACK_MAX_BLOCKS);
+        let mut listed = 0;
+        for block in &blocks[..SLACK_MAX_BLOCKS - 1] {
+            let text = block["text"]["text"].as_str().unwrap();
+            assert!(text.chars().count() <= SLACK_SECTION_CHARS);
+            if block["type"] == "header" {
+                assert!(text.chars().count() <= 150);
+            } else {
+                assert!(!text.contains('<') && !text.contains('>'));
+                listed += text.lines().skip(1).count();
+            }
+        }
+        let more = blocks[SLACK_MAX_BLOCKS - 1]["elements"][0]["text"]
+            .as_str()
+            .unwrap();
+        assert_eq!(more, format!("…and {} more", events.len() - listed));
+    }
+}
@@ -0,0 +1,168 @@
+// src/report.rs
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{
+    cli::ReportFormat,
+    metrics::{TestResult, TestStatus},
+    runner::TestCycleSummary,
+};
+
+/// How many of the most failing bootnodes the report lists.
+const WORST_BOOTNODES: usize = 10;
+
+#[derive(Debug, Default, Serialize)]
+pub struct NetworkStats {
+    pub tests: u64,
+    pub passed: u64,
+    pub success_rate: f64,
+    pub avg_duration_ms: u64,
+    #[serde(skip)]
+    total_duration_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BootnodeStats {
+    pub network: String,
+    pub operator: String,
+    pub bootnode: String,
+    pub tests: u64,
+    pub failed: u64,
+}
+
+/// Totals over every cycle of a run, printed when it ends. Deferred tests are
+/// left out, as they say nothing about the bootnode.
+#[derive(Debug, Default, Serialize)]
+pub struct FinalReport {
+    pub cycles: u64,
+    pub tests: u64,
+    pub passed: u64,
+    pub networks: BTreeMap<String, NetworkStats>,
+    pub worst_bootnodes: Vec<BootnodeStats>,
+    #[serde(skip)]
+    bootnodes: BTreeMap<(String, String, String), (u64, u64)>,
+}
+
+impl FinalReport {
+    pub fn add_cycle(&mut self, summary: &TestCycleSummary) {
+        self.cycles += 1;
+        for result in &summary.results {
+            self.add_result(result);
+        }
+    }
+
+    fn add_result(&mut self, result: &TestResult) {
+        if matches!(result.status, TestStatus::Deferred) {
+            return;
+        }
+        self.tests += 1;
+        self.passed += result.valid as u64;
+
+        let network = self.networks.entry(result.network.clone()).or_default();
+        network.tests += 1;
+        network.passed += result.valid as u64;
+        network.total_duration_ms += result.test_duration_ms;
+
+        let (tests, failed) = self
+            .bootnodes
+            .entry((
+                result.network.clone(),
+                result.id.clone(),
+                result.bootnode.clone(),
+            ))
+            .or_default();
+        *tests += 1;
+        *failed += !result.valid as u64;
+    }
+
+    /// Fills in the derived fields once all cycles are added.
+    fn finish(&mut self) {
+        for network in self.networks.values_mut() {
+            network.success_rate = rate(network.passed, network.tests);
+            network.avg_duration_ms = network.total_duration_ms / network.tests.max(1);
+        }
+
+        let mut worst: Vec<_> = self
+            .bootnodes
+            .iter()
+            .filter(|(_, (_, failed))| *failed > 0)
+            .map(
+                |((network, operator, bootnode), (tests, failed))| BootnodeStats {
+                    network: network.clone(),
+                    operator: operator.clone(),
+                    bootnode: bootnode.clone(),
+                    tests: *tests,
+                    failed: *failed,
+                },
+            )
+            .collect();
+        worst.sort_by(|a, b| b.failed.cmp(&a.failed).then(a.tests.cmp(&b.tests)));
+        worst.truncate(WORST_BOOTNODES);
+        self.worst_bootnodes = worst;
+    }
+
+    pub fn print(mut self, format: ReportFormat) -> Result<()> {
+        self.finish();
+        match format {
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&self)?),
+            ReportFormat::Text => self.print_text(),
+        }
+        Ok(())
+    }
+
+    fn print_text(&self) {
+        println!(
+            "Final report: {} cycles, {} tests, {} passed ({:.1}%)",
+            self.cycles,
+            self.tests,
+            self.passed,
+            rate(self.passed, self.tests) * 100.0
+        );
+        if self.networks.is_empty() {
+            return;
+        }
+
+        // lowest success rate first
+        let mut networks: Vec<_> = self.networks.iter().collect();
+        networks.sort_by(|a, b| a.1.success_rate.total_cmp(&b.1.success_rate));
+        println!();
+        println!(
+            "{:<32} {:>7} {:>7} {:>8} {:>10}",
+            "network", "tests", "passed", "success", "avg ms"
+        );
+        for (name, network) in networks {
+            println!(
+                "{:<32} {:>7} {:>7} {:>7.1}% {:>10}",
+                name,
+                network.tests,
+                network.passed,
+                network.success_rate * 100.0,
+                network.avg_duration_ms
+            );
+        }
+
+        if !self.worst_bootnodes.is_empty() {
+            println!();
+            println!("Most failing bootnodes:");
+            for bootnode in &self.worst_bootnodes {
+                println!(
+                    "  {}/{} {}: {}/{} failed",
+                    bootnode.operator,
+                    bootnode.network,
+                    bootnode.bootnode,
+                    bootnode.failed,
+                    bootnode.tests
+                );
+            }
+        }
+    }
+}
+
+fn rate(passed: u64, tests: u64) -> f64 {
+    if tests == 0 {
+        0.0
+    } else {
+        passed as f64 / tests as f64
+    }
+}
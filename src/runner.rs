@@ -0,0 +1,936 @@
+// src/runner.rs
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use futures::future::{join_all, BoxFuture};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    future::Future,
+    io::Write,
+    path::Path,
+    sync::Arc,
+};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, info_span, Instrument};
+
+use crate::{
+    bootnode::{node_data_dir, parse_multiaddr, test_bootnode, NodePlan},
+    cli::{self, Cli},
+    geo::GeoIp,
+    metrics::{self, OnDemand, TestRequest, TestRequestError, TestResult, TestStatus},
+    notify::Notifier,
+    storage::{self, CsvExport, JsonlLog, ResultsDb},
+};
+
+/// (network, operator, bootnode) of a single test.
+pub type TestKey = (String, String, String);
+
+/// One bootnode to test in a cycle.
+struct TestJob {
+    network: String,
+    operator: String,
+    bootnode: String,
+    network_config: Arc<cli::NetworkConfig>,
+}
+
+impl TestJob {
+    fn key(&self) -> TestKey {
+        (
+            self.network.clone(),
+            self.operator.clone(),
+            self.bootnode.clone(),
+        )
+    }
+}
+
+/// Binary a network's nodes run, as used for `--max-concurrent-*`.
+fn binary_kind(command_id: &str) -> &'static str {
+    match command_id {
+        "parachain" => "parachain",
+        "encointer" => "encointer",
+        _ => "relay",
+    }
+}
+
+/// State shared by every test cycle.
+pub struct CycleContext {
+    pub cli: Cli,
+    pub metrics_state: Arc<metrics::MetricsState>,
+    /// `--max-concurrent`, shared by all tests
+    pub semaphore: Arc<Semaphore>,
+    /// `--max-concurrent-*` limits, by binary kind; a kind without an entry is
+    /// only bound by `semaphore`
+    pub binary_semaphores: HashMap<&'static str, Arc<Semaphore>>,
+    pub notifier: Notifier,
+    pub results_db: Option<ResultsDb>,
+    pub jsonl_log: Option<Arc<JsonlLog>>,
+    pub csv_export: Option<CsvExport>,
+    pub geoip: Option<Arc<GeoIp>>,
+    /// Cancelling it stops starting new tests and kills running nodes.
+    pub shutdown: CancellationToken,
+}
+
+impl CycleContext {
+    /// Opens the notifier and the optional outputs configured in `cli`.
+    pub fn new(
+        cli: &Cli,
+        metrics_state: Arc<metrics::MetricsState>,
+        shutdown: CancellationToken,
+    ) -> Result<Self> {
+        Ok(Self {
+            cli: cli.clone(),
+            metrics_state,
+            semaphore: Arc::new(Semaphore::new(cli.max_concurrent)),
+            binary_semaphores: [
+                ("relay", cli.max_concurrent_relay),
+                ("parachain", cli.max_concurrent_parachain),
+                ("encointer", cli.max_concurrent_encointer),
+            ]
+            .into_iter()
+            .filter_map(|(kind, limit)| Some((kind, Arc::new(Semaphore::new(limit?)))))
+            .collect(),
+            notifier: Notifier::new(cli)?,
+            results_db: cli.sqlite.as_deref().map(ResultsDb::open).transpose()?,
+            jsonl_log: cli
+                .jsonl_output
+                .as_deref()
+                .map(JsonlLog::open)
+                .transpose()?
+                .map(Arc::new),
+            csv_export: cli.csv_output.as_deref().map(CsvExport::new),
+            geoip: GeoIp::open(cli)?.map(Arc::new),
+            shutdown,
+        })
+    }
+}
+
+/// Runs single tests on request, outside of the cycles. They share the
+/// `--max-concurrent` limit with the cycles but aren't recorded anywhere.
+pub struct OnDemandTester {
+    cli: Cli,
+    semaphore: Arc<Semaphore>,
+    shutdown: CancellationToken,
+    networks: std::sync::RwLock<HashMap<String, Arc<cli::NetworkConfig>>>,
+}
+
+impl OnDemandTester {
+    pub fn new(ctx: &CycleContext, bootnodes: &cli::BootnodesConfig) -> Self {
+        let tester = Self {
+            cli: ctx.cli.clone(),
+            semaphore: Arc::clone(&ctx.semaphore),
+            shutdown: ctx.shutdown.clone(),
+            networks: Default::default(),
+        };
+        tester.update(bootnodes);
+        tester
+    }
+
+    /// Takes the networks of a reloaded bootnodes config.
+    pub fn update(&self, bootnodes: &cli::BootnodesConfig) {
+        let networks = bootnodes
+            .networks
+            .iter()
+            .map(|(network, config)| (network.clone(), Arc::new(config.clone())))
+            .collect();
+        *self
+            .networks
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = networks;
+    }
+
+    /// The settings of the request's network, if the request is one to run.
+    fn check(&self, request: &TestRequest) -> Result<Arc<cli::NetworkConfig>, TestRequestError> {
+        // both end up in data dir names
+        for (field, value) in [
+            ("operator", &request.operator),
+            ("network", &request.network),
+        ] {
+            if value.is_empty() || value.contains(['/', '\\']) || value.contains("..") {
+                return Err(TestRequestError::InvalidName(field, value.clone()));
+            }
+        }
+        self.networks
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&request.network)
+            .cloned()
+            .ok_or_else(|| TestRequestError::UnknownNetwork(request.network.clone()))
+    }
+}
+
+impl OnDemand for OnDemandTester {
+    /// Tests the bootnode once, with the settings of its network in the
+    /// bootnodes config.
+    fn run(&self, request: TestRequest) -> BoxFuture<'_, Result<TestResult>> {
+        Box::pin(async move {
+            let network_config = self.check(&request)?;
+            let _permit = tokio::select! {
+                permit = self.semaphore.acquire() => permit?,
+                _ = self.shutdown.cancelled() => anyhow::bail!("Shutting down"),
+            };
+            let network_config = cli::NetworkConfig {
+                command_id: request.command_id,
+                ..(*network_config).clone()
+            };
+            let cli = Cli {
+                test_retries: 1,
+                ..self.cli.clone()
+            };
+            test_bootnode(
+                &cli,
+                &request.operator,
+                &request.network,
+                &request.bootnode,
+                &network_config,
+                &self.shutdown,
+            )
+            .await
+        })
+    }
+}
+
+/// Every bootnode in the config that passes the `--networks`/`--operators` filters.
+fn collect_jobs(cli: &Cli, bootnodes: &cli::BootnodesConfig) -> Vec<TestJob> {
+    let mut jobs = Vec::new();
+    for (network, network_config) in &bootnodes.networks {
+        if !cli.tests_network(network) {
+            continue;
+        }
+        let network_config = Arc::new(network_config.clone());
+        for (operator, bootnodes) in &network_config.members {
+            if !cli.tests_operator(operator) {
+                continue;
+            }
+            for bootnode in bootnodes {
+                jobs.push(TestJob {
+                    network: network.clone(),
+                    operator: operator.clone(),
+                    bootnode: bootnode.clone(),
+                    network_config: Arc::clone(&network_config),
+                });
+            }
+        }
+    }
+    jobs
+}
+
+/// Logs the command a test would run, or why it can't, without claiming ports
+/// or spawning anything. Ports and data dirs are assigned at run time, so they
+/// are shown as placeholders.
+fn plan_test(cli: &Cli, job: &TestJob) -> Result<()> {
+    let plan = NodePlan::new(cli, &job.network, &job.network_config)?;
+    parse_multiaddr(&job.bootnode)?;
+    let cmd = plan.command(
+        &job.bootnode,
+        0,
+        0,
+        &node_data_dir(&cli.data_dir, &job.operator, &job.network, 0),
+    );
+    info!("[dry run] {}/{}: {:?}", job.operator, job.network, cmd);
+    Ok(())
+}
+
+/// Tests every bootnode in `bootnodes` that passes the filters in `ctx.cli`,
+/// bounded by `--max-concurrent`, and merges the results into `results.json`.
+/// Bootnodes in `previously_failed` are tested first.
+///
+/// The port pool must be set up with [`crate::init_port_pool`] beforehand.
+pub async fn run_test_cycle(
+    ctx: &CycleContext,
+    bootnodes: &cli::BootnodesConfig,
+    previously_failed: &[TestKey],
+) -> Result<TestCycleSummary> {
+    run_test_cycle_with(
+        ctx,
+        bootnodes,
+        previously_failed,
+        |cli, job, shutdown| async move {
+            test_bootnode(
+                &cli,
+                &job.operator,
+                &job.network,
+                &job.bootnode,
+                &job.network_config,
+                &shutdown,
+            )
+            .await
+        },
+    )
+    .await
+}
+
+/// [`run_test_cycle`] with `test` in place of [`test_bootnode`], so the cycle
+/// can be exercised without spawning nodes.
+async fn run_test_cycle_with<F, Fut>(
+    ctx: &CycleContext,
+    bootnodes: &cli::BootnodesConfig,
+    previously_failed: &[TestKey],
+    test: F,
+) -> Result<TestCycleSummary>
+where
+    F: Fn(Cli, Arc<TestJob>, CancellationToken) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<TestResult>> + Send,
+{
+    let cli = &ctx.cli;
+    let mut jobs = collect_jobs(cli, bootnodes);
+    // recheck last cycle's failures first so recoveries show up early;
+    // the sort is stable, so everything else keeps its usual order
+    jobs.sort_by_key(|job| !previously_failed.contains(&job.key()));
+
+    let total_tests = jobs.len();
+    let mut success_count = 0;
+    let mut deferred_count = 0;
+    let mut failed_tests = Vec::new();
+
+    if cli.dry_run {
+        for job in &jobs {
+            match plan_test(cli, job) {
+                Ok(()) => success_count += 1,
+                Err(e) => {
+                    error!(
+                        "[dry run] {}/{} {}: {}",
+                        job.operator, job.network, job.bootnode, e
+                    );
+                    failed_tests.push(job.key());
+                }
+            }
+        }
+        return Ok(TestCycleSummary {
+            total_tests,
+            success_count,
+            deferred_count,
+            failed_tests,
+            results: Vec::new(),
+        });
+    }
+
+    // (network, bootnode) -> operators whose entry reuses the result of the first one
+    let mut duplicates: HashMap<(String, String), Vec<String>> = HashMap::new();
+    if cli.dedupe {
+        // (network, bootnode) -> operator whose entry is tested
+        let mut tested: HashMap<(String, String), String> = HashMap::new();
+        let before = jobs.len();
+        jobs.retain(|job| {
+            let bootnode = (job.network.clone(), job.bootnode.clone());
+            let Some(operator) = tested.get(&bootnode) else {
+                tested.insert(bootnode, job.operator.clone());
+                return true;
+            };
+            // an operator listing a bootnode twice still gets a single result
+            let copies = duplicates.entry(bootnode).or_default();
+            if *operator != job.operator && !copies.contains(&job.operator) {
+                copies.push(job.operator.clone());
+            }
+            false
+        });
+        let collapsed = before - jobs.len();
+        if collapsed > 0 {
+            info!("Collapsed {} duplicate bootnode entries", collapsed);
+        }
+    }
+
+    let jobs: Vec<Arc<TestJob>> = jobs.into_iter().map(Arc::new).collect();
+    let mut outcomes = run_jobs(ctx, &jobs, &test).await;
+
+    // failures get their second chance before anything is recorded, so a
+    // blip the recheck absorbs is neither counted nor alerted on
+    if cli.recheck_failures && !ctx.shutdown.is_cancelled() {
+        let failed: Vec<usize> = outcomes
+            .iter()
+            .enumerate()
+            .filter(|(_, outcome)| {
+                outcome.as_ref().is_some_and(|result| {
+                    !result.valid && !matches!(result.status, TestStatus::Deferred)
+                })
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if !failed.is_empty() {
+            info!("Rechecking {} failed bootnodes", failed.len());
+            let recheck_jobs: Vec<_> = failed.iter().map(|&i| Arc::clone(&jobs[i])).collect();
+            let rechecks = run_jobs(ctx, &recheck_jobs, &test).await;
+            for (i, recheck) in failed.into_iter().zip(rechecks) {
+                // a deferred or cancelled recheck keeps the first failure
+                let Some(recheck) = recheck else {
+                    continue;
+                };
+                if matches!(recheck.status, TestStatus::Deferred) {
+                    continue;
+                }
+                if recheck.valid {
+                    info!(
+                        "{}/{} {} passed on recheck",
+                        recheck.id, recheck.network, recheck.bootnode
+                    );
+                }
+                outcomes[i] = Some(recheck);
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    for (job, outcome) in jobs.iter().zip(outcomes) {
+        let Some(mut test_result) = outcome else {
+            continue;
+        };
+        let command_id = job.network_config.command_id.as_str();
+        // a deferred test says nothing about the bootnode
+        if !matches!(test_result.status, TestStatus::Deferred) {
+            record_result(
+                &ctx.metrics_state,
+                ctx.geoip.as_deref(),
+                ctx.jsonl_log.as_deref(),
+                command_id,
+                &mut test_result,
+            );
+        }
+        let copies = duplicates
+            .remove(&(test_result.network.clone(), test_result.bootnode.clone()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|operator| {
+                let mut copy = TestResult {
+                    id: operator,
+                    ..test_result.clone()
+                };
+                if !matches!(copy.status, TestStatus::Deferred) {
+                    record_result(
+                        &ctx.metrics_state,
+                        ctx.geoip.as_deref(),
+                        ctx.jsonl_log.as_deref(),
+                        command_id,
+                        &mut copy,
+                    );
+                }
+                copy
+            })
+            .collect::<Vec<_>>();
+
+        for test_result in std::iter::once(test_result).chain(copies) {
+            if matches!(test_result.status, TestStatus::Deferred) {
+                deferred_count += 1;
+            } else {
+                let critical = bootnodes
+                    .networks
+                    .get(&test_result.network)
+                    .is_some_and(|network| network.critical);
+                ctx.notifier.handle_result(&test_result, critical);
+                if test_result.valid {
+                    success_count += 1;
+                } else {
+                    failed_tests.push((
+                        test_result.network.clone(),
+                        test_result.id.clone(),
+                        test_result.bootnode.clone(),
+                    ));
+                }
+            }
+            if let Some(db) = &ctx.results_db {
+                if let Err(e) = db.insert(&test_result) {
+                    error!("Failed to store result in SQLite: {}", e);
+                }
+            }
+            results.push(test_result);
+        }
+    }
+
+    ctx.notifier.flush_cycle().await;
+
+    if cli.group_by_peer_id {
+        report_transports(&ctx.metrics_state, &results);
+    }
+
+    update_results(
+        &cli.output_dir.join("results.json"),
+        &results,
+        cli.keep_history,
+    )
+    .await?;
+
+    if let Some(csv_export) = &ctx.csv_export {
+        if let Err(e) = csv_export.write_cycle(&results) {
+            error!("Failed to write CSV output: {}", e);
+        }
+    }
+
+    Ok(TestCycleSummary {
+        total_tests,
+        success_count,
+        deferred_count,
+        failed_tests,
+        results,
+    })
+}
+
+/// Runs `test` for every job, bounded by the concurrency limits, and returns
+/// the results in job order. `None` for tests dropped on shutdown and for
+/// errors of the tester itself; a panicked test becomes a failed result.
+async fn run_jobs<F, Fut>(
+    ctx: &CycleContext,
+    jobs: &[Arc<TestJob>],
+    test: &F,
+) -> Vec<Option<TestResult>>
+where
+    F: Fn(Cli, Arc<TestJob>, CancellationToken) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<TestResult>> + Send,
+{
+    // one per operator, so an operator with many bootnodes can't take every
+    // slot while the others wait
+    let mut operator_semaphores: HashMap<String, Arc<Semaphore>> = HashMap::new();
+
+    let cli = &ctx.cli;
+    let mut tasks = Vec::new();
+    for job in jobs {
+        let job = Arc::clone(job);
+        let test = test.clone();
+        let cli = cli.clone();
+        let semaphore = Arc::clone(&ctx.semaphore);
+        let binary_semaphore = ctx
+            .binary_semaphores
+            .get(binary_kind(&job.network_config.command_id))
+            .cloned();
+        let operator_semaphore = cli.max_concurrent_per_operator.map(|limit| {
+            Arc::clone(
+                operator_semaphores
+                    .entry(job.operator.clone())
+                    .or_insert_with(|| Arc::new(Semaphore::new(limit))),
+            )
+        });
+        let metrics = Arc::clone(&ctx.metrics_state);
+        let shutdown = ctx.shutdown.clone();
+
+        let span = info_span!(
+            "test",
+            network = %job.network,
+            operator = %job.operator,
+            bootnode = %job.bootnode
+        );
+        tasks.push(tokio::spawn(
+            async move {
+                // the operator's and the binary's own limits are taken first, so
+                // tests waiting on a busy operator or binary don't hold global
+                // permits that others could use; the semaphores are fair, so tests
+                // still start roughly in job order
+                let permits = async {
+                    let operator_permit = match operator_semaphore {
+                        Some(operator_semaphore) => Some(operator_semaphore.acquire_owned().await?),
+                        None => None,
+                    };
+                    let binary_permit = match binary_semaphore {
+                        Some(binary_semaphore) => Some(binary_semaphore.acquire_owned().await?),
+                        None => None,
+                    };
+                    let permit = Arc::clone(&semaphore).acquire_owned().await?;
+                    Ok::<_, anyhow::Error>((operator_permit, binary_permit, permit))
+                };
+                let permits = tokio::select! {
+                    permits = permits => permits?,
+                    // tests that haven't started yet are dropped silently on shutdown
+                    _ = shutdown.cancelled() => return Ok(None),
+                };
+
+                let in_use = || cli.max_concurrent - semaphore.available_permits();
+                metrics.record_concurrency(in_use(), cli.max_concurrent);
+
+                let result = test(cli.clone(), Arc::clone(&job), shutdown.clone()).await;
+
+                drop(permits);
+                metrics.record_concurrency(in_use(), cli.max_concurrent);
+                result.map(Some)
+            }
+            .instrument(span),
+        ));
+    }
+
+    let mut results = Vec::new();
+    for (job, result) in jobs.iter().zip(join_all(tasks).await) {
+        // a panic in one test must not take down the rest of the cycle; it
+        // fails that bootnode like any other error
+        let result = match result {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                error!("Test failed: {}", e);
+                None
+            }
+            Err(e) => {
+                error!(
+                    "Test task for {}/{} {} panicked: {}",
+                    job.operator, job.network, job.bootnode, e
+                );
+                Some(TestResult::failed(
+                    &job.operator,
+                    &job.network,
+                    &job.bootnode,
+                    TestStatus::Panicked,
+                    e.to_string(),
+                ))
+            }
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Records a finished test in the metrics, setting its uptime, and in the
+/// JSON lines log.
+fn record_result(
+    metrics: &metrics::MetricsState,
+    geoip: Option<&GeoIp>,
+    jsonl_log: Option<&JsonlLog>,
+    command_id: &str,
+    result: &mut TestResult,
+) {
+    let uptime = metrics.record_test_result(
+        &result.network,
+        &result.id,
+        &result.bootnode,
+        command_id,
+        result,
+    );
+    result.uptime_ratio = Some(uptime);
+    result.consecutive_failures = Some(metrics.record_consecutive_failures(
+        &result.network,
+        &result.id,
+        &result.bootnode,
+        result.valid,
+    ));
+
+    if let (Some(geoip), Some(ip)) = (geoip, result.ip) {
+        let geo = geoip.lookup(ip);
+        metrics.record_geo(&result.network, &result.id, &result.bootnode, geo);
+    }
+
+    if let Some(jsonl_log) = jsonl_log {
+        if let Err(e) = jsonl_log.append(result) {
+            error!("Failed to append result to JSON lines output: {}", e);
+        }
+    }
+}
+
+/// Groups results by the peer id in their multiaddr, so one node advertised
+/// over several transports is reported as a single node with per-transport
+/// results.
+fn report_transports(metrics_state: &metrics::MetricsState, results: &[TestResult]) {
+    // (network, operator, peer id) -> (protocol, passed) per advertised address
+    let mut nodes: BTreeMap<TestKey, Vec<(&str, bool)>> = BTreeMap::new();
+    for result in results {
+        let Ok(addr) = parse_multiaddr(&result.bootnode) else {
+            continue;
+        };
+        if matches!(result.status, TestStatus::Deferred) {
+            continue;
+        }
+        nodes
+            .entry((result.network.clone(), result.id.clone(), addr.peer_id))
+            .or_default()
+            .push((addr.transport.label(), result.valid));
+    }
+
+    for ((network, operator, peer_id), transports) in nodes {
+        for (protocol, valid) in &transports {
+            metrics_state.record_connection_type(&network, &operator, &peer_id, protocol, *valid);
+        }
+        if transports.len() > 1 {
+            let summary = transports
+                .iter()
+                .map(|(protocol, valid)| {
+                    format!("{}={}", protocol, if *valid { "ok" } else { "failed" })
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            info!("{}/{} peer {}: {}", operator, network, peer_id, summary);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TestCycleSummary {
+    pub total_tests: usize,
+    pub success_count: usize,
+    /// tests skipped for lack of memory, neither passed nor failed
+    pub deferred_count: usize,
+    pub failed_tests: Vec<TestKey>,
+    pub results: Vec<TestResult>,
+}
+
+/// Merges a cycle's results into `results.json`, keyed by operator and network.
+async fn update_results(
+    output_file: &Path,
+    results: &[TestResult],
+    keep_history: Option<u64>,
+) -> Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    // waiting for another instance's lock must not hold up a runtime worker
+    let output_file = output_file.to_path_buf();
+    let results = results.to_vec();
+    tokio::task::spawn_blocking(move || write_results(&output_file, &results, keep_history)).await?
+}
+
+/// Blocking part of [`update_results`].
+fn write_results(
+    output_file: &Path,
+    results: &[TestResult],
+    keep_history: Option<u64>,
+) -> Result<()> {
+    // several instances may share an output dir, so serialize the whole
+    // read-modify-write, not just the final rename
+    let lock_file = File::create(output_file.with_extension("lock"))
+        .context("Failed to create results lock file")?;
+    lock_file
+        .lock_exclusive()
+        .context("Failed to lock results file")?;
+
+    let content = if output_file.exists() {
+        fs::read_to_string(output_file)?
+    } else {
+        "{}".to_string()
+    };
+
+    let mut json: serde_json::Value = serde_json::from_str(&content)?;
+
+    if let serde_json::Value::Object(ref mut map) = json {
+        for result in results {
+            let operator_obj = map
+                .entry(result.id.as_str())
+                .or_insert(serde_json::json!({}))
+                .as_object_mut()
+                .context("Invalid JSON structure")?;
+
+            let value = serde_json::to_value(result)?;
+            match keep_history {
+                None => {
+                    operator_obj.insert(result.network.clone(), value);
+                }
+                Some(limit) => {
+                    // a snapshot written before history was enabled becomes the first entry
+                    let entry = operator_obj
+                        .entry(result.network.as_str())
+                        .or_insert_with(|| serde_json::json!([]));
+                    if !entry.is_array() {
+                        *entry = serde_json::Value::Array(vec![entry.take()]);
+                    }
+                    let history = entry.as_array_mut().context("Invalid JSON structure")?;
+                    history.push(value);
+                    let excess = history.len().saturating_sub(limit as usize);
+                    history.drain(..excess);
+                }
+            }
+        }
+    }
+
+    let tmp_file = storage::temp_sibling(output_file);
+    let mut file = File::create(&tmp_file)?;
+    file.write_all(serde_json::to_string_pretty(&json)?.as_bytes())?;
+    fs::rename(tmp_file, output_file)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::time::Duration;
+
+    const PANICKING_BOOTNODE: &str = "/dns/panics.example/tcp/30333/p2p/12D3KooWPanic";
+
+    fn test_ctx(name: &str, args: &[&str]) -> CycleContext {
+        let output_dir =
+            std::env::temp_dir().join(format!("bootyspector-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&output_dir).unwrap();
+        let cli = Cli::parse_from(
+            [
+                "bootyspector".as_ref(),
+                "--output-dir".as_ref(),
+                output_dir.as_os_str(),
+            ]
+            .into_iter()
+            .chain(args.iter().map(|arg| arg.as_ref())),
+        );
+        let (metrics_state, _) = metrics::MetricsState::new(Duration::from_secs(3600)).unwrap();
+        CycleContext::new(&cli, Arc::new(metrics_state), CancellationToken::new()).unwrap()
+    }
+
+    fn bootnodes(members: &[&str]) -> cli::BootnodesConfig {
+        let network = cli::NetworkConfig {
+            command_id: "polkadot".to_string(),
+            members: HashMap::from([(
+                "operator".to_string(),
+                members.iter().map(ToString::to_string).collect(),
+            )]),
+            ..Default::default()
+        };
+        cli::BootnodesConfig {
+            networks: HashMap::from([("polkadot".to_string(), network)]),
+        }
+    }
+
+    fn passed(job: &TestJob) -> TestResult {
+        TestResult {
+            valid: true,
+            error_details: None,
+            ..TestResult::failed(
+                &job.operator,
+                &job.network,
+                &job.bootnode,
+                TestStatus::Success,
+                String::new(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_test_fails_only_its_bootnode() {
+        let ctx = test_ctx("panic", &[]);
+        let passing = [
+            "/dns/a.example/tcp/30333/p2p/12D3KooWA",
+            "/dns/b.example/tcp/30333/p2p/12D3KooWB",
+        ];
+        let config = bootnodes(&[passing[0], PANICKING_BOOTNODE, passing[1]]);
+
+        let summary = run_test_cycle_with(&ctx, &config, &[], |_, job, _| async move {
+            if job.bootnode == PANICKING_BOOTNODE {
+                panic!("injected panic");
+            }
+            Ok(passed(&job))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(summary.total_tests, 3);
+        assert_eq!(summary.success_count, 2);
+        assert_eq!(
+            summary.failed_tests,
+            vec![(
+                "polkadot".to_string(),
+                "operator".to_string(),
+                PANICKING_BOOTNODE.to_string()
+            )]
+        );
+        assert_eq!(summary.results.len(), 3);
+        let panicked = summary
+            .results
+            .iter()
+            .find(|result| result.bootnode == PANICKING_BOOTNODE)
+            .unwrap();
+        assert!(matches!(panicked.status, TestStatus::Panicked));
+        // recorded like any other failure
+        assert_eq!(panicked.consecutive_failures, Some(1));
+        for bootnode in passing {
+            assert!(summary
+                .results
+                .iter()
+                .any(|result| result.bootnode == bootnode && result.valid));
+        }
+
+        let _ = fs::remove_dir_all(&ctx.cli.output_dir);
+    }
+
+    #[tokio::test]
+    async fn failure_that_passes_on_recheck_is_recorded_once() {
+        let ctx = test_ctx("recheck", &["--recheck-failures"]);
+        let flaky = "/dns/flaky.example/tcp/30333/p2p/12D3KooWFlaky";
+        let config = bootnodes(&[flaky]);
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let counter = Arc::clone(&attempts);
+        let summary = run_test_cycle_with(&ctx, &config, &[], move |_, job, _| {
+            let attempt = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                if attempt == 0 {
+                    return Ok(TestResult::failed(
+                        &job.operator,
+                        &job.network,
+                        &job.bootnode,
+                        TestStatus::Timeout,
+                        "timed out".to_string(),
+                    ));
+                }
+                Ok(passed(&job))
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(summary.success_count, 1);
+        assert!(summary.failed_tests.is_empty());
+        assert_eq!(summary.results.len(), 1);
+        // the first failure never reached the metrics
+        assert_eq!(summary.results[0].consecutive_failures, Some(0));
+
+        let _ = fs::remove_dir_all(&ctx.cli.output_dir);
+    }
+
+    #[test]
+    fn on_demand_requests_are_checked() {
+        let ctx = test_ctx("on-demand", &[]);
+        let tester = OnDemandTester::new(&ctx, &bootnodes(&[]));
+        let request = |operator: &str, network: &str| TestRequest {
+            network: network.to_string(),
+            operator: operator.to_string(),
+            bootnode: "/dns/a.example/tcp/30333/p2p/12D3KooWA".to_string(),
+            command_id: "polkadot".to_string(),
+        };
+
+        assert!(tester.check(&request("operator", "polkadot")).is_ok());
+        for (operator, network) in [
+            ("../operator", "polkadot"),
+            ("op/erator", "polkadot"),
+            ("operator", "..\\polkadot"),
+            ("", "polkadot"),
+        ] {
+            assert!(matches!(
+                tester.check(&request(operator, network)),
+                Err(TestRequestError::InvalidName(..))
+            ));
+        }
+        assert!(matches!(
+            tester.check(&request("operator", "kusama")),
+            Err(TestRequestError::UnknownNetwork(_))
+        ));
+
+        let _ = fs::remove_dir_all(&ctx.cli.output_dir);
+    }
+
+    #[tokio::test]
+    async fn dedupe_records_each_operator_entry_once() {
+        let ctx = test_ctx("dedupe", &["--dedupe"]);
+        let bootnode = "/dns/shared.example/tcp/30333/p2p/12D3KooWShared";
+        let mut config = bootnodes(&[bootnode, bootnode]);
+        config
+            .networks
+            .get_mut("polkadot")
+            .unwrap()
+            .members
+            .insert("other".to_string(), vec![bootnode.to_string()]);
+
+        let summary = run_test_cycle_with(&ctx, &config, &[], |_, job, _| async move {
+            Ok(TestResult::failed(
+                &job.operator,
+                &job.network,
+                &job.bootnode,
+                TestStatus::Timeout,
+                "timed out".to_string(),
+            ))
+        })
+        .await
+        .unwrap();
+
+        let mut operators: Vec<_> = summary
+            .results
+            .iter()
+            .map(|result| result.id.as_str())
+            .collect();
+        operators.sort();
+        assert_eq!(operators, ["operator", "other"]);
+        for result in &summary.results {
+            assert_eq!(result.consecutive_failures, Some(1));
+        }
+
+        let _ = fs::remove_dir_all(&ctx.cli.output_dir);
+    }
+}
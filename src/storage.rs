@@ -0,0 +1,186 @@
+// src/storage.rs
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{
+    metrics::{TestResult, TestStatus},
+    runner::TestKey,
+};
+
+/// Append-only history of every test result, for uptime and flapping analysis.
+pub struct ResultsDb {
+    conn: Mutex<Connection>,
+}
+
+impl ResultsDb {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {:?}", path))?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS test_results (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                network TEXT NOT NULL,
+                operator TEXT NOT NULL,
+                bootnode TEXT NOT NULL,
+                valid INTEGER NOT NULL,
+                discovered_peers INTEGER NOT NULL,
+                test_duration_ms INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                error_details TEXT
+            );
+            CREATE INDEX IF NOT EXISTS test_results_bootnode
+                ON test_results (network, operator, bootnode, timestamp);",
+        )
+        .context("Failed to create test_results table")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn insert(&self, result: &TestResult) -> Result<()> {
+        let status = serde_json::to_value(&result.status)?;
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+
+        conn.execute(
+            "INSERT INTO test_results (
+                timestamp, network, operator, bootnode, valid,
+                discovered_peers, test_duration_ms, status, error_details
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                result.timestamp,
+                result.network,
+                result.id,
+                result.bootnode,
+                result.valid,
+                result.discovered_peers as i64,
+                result.test_duration_ms as i64,
+                status.as_str().unwrap_or_default(),
+                result.error_details,
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Append-only JSON-lines stream of test results for log shippers.
+pub struct JsonlLog {
+    file: Mutex<File>,
+}
+
+impl JsonlLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open JSON lines output {:?}", path))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends the result as one line. The line is written with a single
+    /// `write_all` on an `O_APPEND` file, so tailing readers never see a
+    /// partial record interleaved with another.
+    pub fn append(&self, result: &TestResult) -> Result<()> {
+        let mut line = serde_json::to_vec(result)?;
+        line.push(b'\n');
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+const CSV_HEADER: &str =
+    "network,operator,bootnode,valid,discovered_peers,test_duration_ms,status,timestamp,error_details";
+
+/// CSV file holding the latest result of every bootnode, rewritten after each cycle.
+pub struct CsvExport {
+    path: PathBuf,
+    /// latest row per bootnode, so bootnodes skipped by a cycle keep theirs
+    rows: Mutex<BTreeMap<TestKey, String>>,
+}
+
+impl CsvExport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            rows: Mutex::default(),
+        }
+    }
+
+    /// Updates the rows of the cycle's bootnodes and rewrites the file.
+    /// Deferred tests keep the bootnode's previous row.
+    pub fn write_cycle(&self, results: &[TestResult]) -> Result<()> {
+        let mut rows = self.rows.lock().unwrap_or_else(PoisonError::into_inner);
+        for result in results {
+            if matches!(result.status, TestStatus::Deferred) {
+                continue;
+            }
+            let status = serde_json::to_value(&result.status)?;
+            let fields = [
+                result.network.as_str(),
+                result.id.as_str(),
+                result.bootnode.as_str(),
+                if result.valid { "true" } else { "false" },
+                &result.discovered_peers.to_string(),
+                &result.test_duration_ms.to_string(),
+                status.as_str().unwrap_or_default(),
+                &result.timestamp,
+                result.error_details.as_deref().unwrap_or_default(),
+            ];
+            let row = fields.map(csv_field).join(",");
+            rows.insert(
+                (
+                    result.network.clone(),
+                    result.id.clone(),
+                    result.bootnode.clone(),
+                ),
+                row,
+            );
+        }
+
+        let mut content = String::from(CSV_HEADER);
+        content.push_str("\r\n");
+        for row in rows.values() {
+            content.push_str(row);
+            content.push_str("\r\n");
+        }
+
+        let tmp_file = temp_sibling(&self.path);
+        fs::write(&tmp_file, content)
+            .with_context(|| format!("Failed to write CSV output {:?}", tmp_file))?;
+        fs::rename(&tmp_file, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Temp file to write `path` through before renaming it into place: hidden,
+/// and unique per file and process, so neither other outputs in the same dir
+/// nor other instances sharing it write to the same temp file.
+pub fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Quotes a field per RFC 4180 if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}